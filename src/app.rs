@@ -1,21 +1,31 @@
 use std::{
+    fs,
+    path::Path,
     sync::mpsc::{Receiver, RecvTimeoutError},
     time::Duration,
 };
 
-use chrono::{prelude::*, TimeZone};
+use anyhow::Context;
+use chrono::prelude::*;
 
 use today::{
-    formatter::{self, Cell, Field, ListFormatter, TodayFormatter, Visibility},
-    parser::program::Program,
+    formatter::{
+        self, template::TemplateFormatter, Cell, DetailFormatter, Field, JsonFormatter, Layout,
+        ListFormatter, TaskFormatter, TodayFormatter, Visibility,
+    },
+    journal::{self, Action, Journal, Operation},
+    parser::{program::Program, query::Query},
     partial_config::Run,
+    recurrence::Recurrence,
     repository::Repository,
-    task::{TaskList, TaskName},
+    task::{ChecklistItem, Priority, Tag, Task, TaskList, TaskName},
+    timezone::Zone,
+    tracking::{format_duration, Minutes},
 };
 
 use crate::{
     cli,
-    commands::{self, Command},
+    commands::{self, Command, EditOptions, OutputFormat, Period, Readiness},
     ui::{self, writers::OutputMode},
     AppPaths,
 };
@@ -23,6 +33,7 @@ use crate::{
 pub struct App {
     config: AppPaths<Run>,
     repo: Box<dyn Repository<Err = std::io::Error>>,
+    journal: Option<Journal>,
     writer: Option<Box<dyn OutputMode>>,
 
     // Events
@@ -38,6 +49,7 @@ impl App {
         Self {
             config,
             repo: Box::new(repo),
+            journal: None,
             file_changed: None,
             writer: None,
             quit: None,
@@ -53,8 +65,31 @@ impl App {
             Command::List => self.list(),
             Command::Today => self.today(),
             Command::Remove(x) => self.remove(&x),
-            Command::Add { name, due } => self.add(name, due),
-            Command::Edit { program } => self.edit(program),
+            Command::Done(x) => self.done(&x),
+            Command::Show(x) => self.show(&x),
+            Command::Note(x) => self.note(&x),
+            Command::Add {
+                name,
+                due,
+                repeat,
+                priority,
+                tags,
+                timezone,
+                estimate,
+            } => self.add(name, due, repeat, priority, tags, timezone, estimate),
+            Command::Edit {
+                program,
+                parse_errors,
+                options,
+            } => self.edit(program, parse_errors, options),
+            Command::EditInEditor(options) => self.edit_in_editor(options),
+            Command::EditFile(path, options) => self.edit_file(&path, options),
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
+            Command::Log(count) => self.log(count),
+            Command::Start(x) => self.start(&x),
+            Command::Stop => self.stop(),
+            Command::Report(period) => self.report(period),
             _ => self.interactive(),
         }
     }
@@ -73,6 +108,14 @@ impl App {
         }
     }
 
+    /// Record every change in `journal`, so that it can be undone.
+    pub fn with_journal(self, journal: Journal) -> Self {
+        Self {
+            journal: Some(journal),
+            ..self
+        }
+    }
+
     pub fn with_writer<W: OutputMode + 'static>(self, writer: W) -> Self {
         Self {
             writer: Some(Box::new(writer)),
@@ -82,15 +125,25 @@ impl App {
 
     fn config_only(&mut self) -> anyhow::Result<()> {
         if let Some(ref mut writer) = self.writer {
-            let output = vec![
+            let output = [
                 format!(
                     "config path: {}",
                     self.config.config.value().to_string_lossy()
                 ),
                 format!("data path: {}", self.config.data.value().to_string_lossy()),
                 format!("watch mode: {:?}", self.config.watch_mode.value()),
+                format!("show completed: {:?}", self.config.show_completed.value()),
+                format!("readiness: {:?}", self.config.readiness.value()),
+                format!("format: {:?}", self.config.format.value()),
+                format!("tags: {:?}", self.config.tags.value()),
+                format!("query: {:?}", self.config.query.value()),
                 format!("config only: {:?}", self.config.config_only.value()),
                 format!("command: {:#?}", self.config.command.value()),
+                format!("list layout: {:?}", self.config.list_layout.value()),
+                format!("today layout: {:?}", self.config.today_layout.value()),
+                format!("timezone: {}", self.config.timezone.value()),
+                format!("backups: {}", self.config.backups.value()),
+                format!("backend: {:?}", self.config.backend.value()),
             ];
 
             let output = output.join("\r\n");
@@ -100,36 +153,45 @@ impl App {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn add(
         &self,
         name: Option<TaskName>,
         due: Option<Option<DateTime<Utc>>>,
+        repeat: Option<Recurrence>,
+        priority: Option<Priority>,
+        tags: Vec<Tag>,
+        timezone: Option<Zone>,
+        estimate: Option<Minutes>,
     ) -> anyhow::Result<()> {
         let name = name
             .or_else(|| TaskName::new(&ui::prompt_name().ok()?))
             .expect("Could not parse or get a correct taskname from user");
 
-        let due: Option<DateTime<Utc>> = due.unwrap_or_else(|| {
-            ui::prompt_due().ok().and_then(|d| {
-                d.and_then(|d| {
-                    ui::prompt_time()
-                        .ok()
-                        .map(|t| Utc.from_local_datetime(&d.and_time(t)).unwrap())
-                })
-            })
-        });
-
-        let mut tasks = TaskList::from(self.repo.all()?);
-
-        cli::add(name, due, &mut tasks)?;
+        let zone = timezone.unwrap_or_else(|| self.zone());
+        let due: Option<DateTime<Utc>> =
+            due.unwrap_or_else(|| ui::prompt_due(zone).ok().flatten().map(|d| zone.to_utc(&d)));
+
+        let task = cli::add(
+            name,
+            due,
+            repeat,
+            priority.unwrap_or_default(),
+            tags,
+            timezone,
+            estimate,
+        );
 
-        Ok(self.repo.save(tasks)?)
+        self.repo.insert(task.clone())?;
+        self.record(vec![Operation::Add(task)])
     }
 
     fn list(&mut self) -> anyhow::Result<()> {
-        let tasks = TaskList::from(self.repo.all()?);
+        let tasks = self.repo.all()?;
         let shortest_id = commands::shortest_id_length(&tasks).max(5);
-        let mut formatter = ListFormatter::new();
+        let mut formatter = ListFormatter::new()
+            .with_layout(self.config.list_layout.value().clone())
+            .with_timezone(self.zone());
 
         let default_cell = Cell::default().with_margin((0, 1));
         formatter.insert(
@@ -139,10 +201,28 @@ impl App {
                 .with_size(formatter::Size::Max(shortest_id)),
         );
         formatter.insert(Field::Name, default_cell.clone().with_margin((0, 0)));
+        formatter.insert(Field::Priority, priority_cell(&tasks, default_cell.clone()));
+        formatter.insert(Field::Progress, progress_cell(&tasks, default_cell.clone()));
+        formatter.insert(Field::Tags, tags_cell(&tasks, default_cell.clone()));
         formatter.insert(Field::Time, default_cell);
 
+        let show_completed = self.show_completed();
+        let listed = tasks
+            .iter()
+            .filter(|x| show_completed || !x.is_completed())
+            .filter(|x| match self.config.readiness.get() {
+                Readiness::All => true,
+                Readiness::Blocked => tasks.is_blocked(x),
+                Readiness::Ready => !tasks.is_blocked(x),
+            })
+            .filter(|x| self.is_match(x));
+        let output = match self.template(self.config.list_layout.value()) {
+            Some(template) => self.format(listed, &tasks, &template),
+            None => self.format(listed, &tasks, &formatter),
+        };
+
         if let Some(ref mut writer) = self.writer {
-            writer.write(&commands::list(&tasks, &formatter))?;
+            writer.write(&output)?;
         }
 
         Ok(())
@@ -175,66 +255,455 @@ impl App {
     }
 
     fn today_impl(&self) -> anyhow::Result<String> {
-        let mut formatter = TodayFormatter::new();
+        let mut formatter = TodayFormatter::new()
+            .with_layout(self.config.today_layout.value().clone())
+            .with_timezone(self.zone());
         formatter.insert(
             Field::Id,
             Cell::default().with_visibility(Visibility::Hidden),
         );
 
-        let tasks = TaskList::from(self.repo.all()?);
-        Ok(commands::list(tasks.today(), &formatter))
+        let tasks = self.repo.all()?;
+        let today = tasks
+            .today()
+            .with_timezone(self.zone())
+            .with_completed(self.show_completed())
+            .filter(|x| self.is_match(x))
+            .collect::<Vec<_>>();
+        let mut output = match self.template(self.config.today_layout.value()) {
+            Some(template) => self.format(today.iter().copied(), &tasks, &template),
+            None => self.format(today.iter().copied(), &tasks, &formatter),
+        };
+        if self.config.format.get() == OutputFormat::Text {
+            if let Some(estimates) = commands::estimates(&today, Utc::now()) {
+                output.push_str(&format!("\n\n{estimates}"));
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// A formatter for the template of `layout`, if it has one.
+    fn template(&self, layout: &Layout) -> Option<TemplateFormatter> {
+        layout.template.clone().map(|x| {
+            TemplateFormatter::new(x)
+                .with_date_format(&layout.date_format)
+                .with_timezone(self.zone())
+        })
+    }
+
+    /// Write `tasks` in the output format asked for, `text` is used for plain text. Ids are
+    /// shortened to what tells all of `all` apart.
+    fn format<'a, T, I>(&self, tasks: I, all: &[Task], text: &T) -> String
+    where
+        T: TaskFormatter,
+        I: IntoIterator<Item = &'a Task>,
+    {
+        let json = || {
+            JsonFormatter::new()
+                .with_timezone(self.zone())
+                .with_id_length(commands::shortest_id_length(all))
+        };
+
+        match self.config.format.get() {
+            OutputFormat::Text => commands::list(tasks, text),
+            OutputFormat::Json => commands::json(tasks, &json()),
+            OutputFormat::Ndjson => commands::list(tasks, &json()),
+        }
+    }
+
+    /// Returns true if `task` has all the tags the user filters on and matches the query.
+    fn is_match(&self, task: &Task) -> bool {
+        let now = Utc::now();
+        self.config.tags.value().iter().all(|x| task.has_tag(x))
+            && self
+                .config
+                .query
+                .value()
+                .as_ref()
+                .is_none_or(|x| x.matches(task, now, self.zone()))
+    }
+
+    /// The time zone dates are entered and shown in.
+    fn zone(&self) -> Zone {
+        *self.config.timezone.value()
+    }
+
+    /// Completed tasks are shown if asked for or if the query filters on them.
+    fn show_completed(&self) -> bool {
+        self.config.show_completed.get()
+            || self
+                .config
+                .query
+                .value()
+                .as_ref()
+                .is_some_and(Query::references_done)
+    }
+
+    fn show(&mut self, id: &str) -> anyhow::Result<()> {
+        let tasks = self.repo.all()?;
+        let removed = match tasks.iter().any(|x| x.id().to_string().starts_with(id)) {
+            true => TaskList::new(),
+            false => self.repo.removed()?,
+        };
+        let task = match removed.is_empty() {
+            true => cli::find(id, &tasks)?,
+            false => cli::find(id, &removed)?,
+        };
+        let zone = self.zone();
+
+        let formatter = DetailFormatter::new()
+            .with_tasks(&tasks)
+            .with_date_format(&self.config.list_layout.value().date_format)
+            .with_timezone(zone);
+        let output = formatter.format(task);
+        self.write(&output)
+    }
+
+    fn note(&self, id: &str) -> anyhow::Result<()> {
+        let tasks = self.repo.all()?;
+        let task = cli::find(id, &tasks)?;
+
+        let notes = ui::edit_text(task.notes().unwrap_or_default(), ".md")?;
+        let new = task.clone().with_notes(Some(notes));
+        if new.notes() == task.notes() {
+            return Ok(());
+        }
+        let operations = self.update(new)?;
+
+        self.record(operations.into_iter().collect())
     }
 
     fn remove(&self, id: &str) -> anyhow::Result<()> {
-        let mut tasks = TaskList::from(self.repo.all()?);
-        cli::remove(id, &mut tasks)?;
+        let tasks = self.repo.all()?;
+        let id = cli::find(id, &tasks)?.id();
+        let removed = self.repo.delete(id)?;
+
+        self.record(removed.into_iter().map(Operation::Remove).collect())
+    }
+
+    fn done(&self, id: &str) -> anyhow::Result<()> {
+        let tasks = self.repo.all()?;
+        let (task, next) = cli::done(id, &tasks, self.zone())?;
+        let operations = self.complete(task, next)?;
+
+        self.record(operations)
+    }
 
-        Ok(self.repo.save(tasks)?)
+    /// Save a completed task and add its next occurrence, if it repeats. Returns the
+    /// operations made.
+    fn complete(&self, task: Task, next: Option<Task>) -> anyhow::Result<Vec<Operation>> {
+        let mut operations = Vec::new();
+        if let Some(old) = self.repo.update(task.clone())? {
+            operations.push(Operation::Edit { old, new: task });
+        }
+        if let Some(next) = next {
+            self.repo.insert(next.clone())?;
+            operations.push(Operation::Add(next));
+        }
+
+        Ok(operations)
     }
 
-    fn edit(&self, programs: Vec<Program>) -> anyhow::Result<()> {
-        let mut tasks = TaskList::from(self.repo.all()?);
+    /// Add `operations` to the journal as one change.
+    fn record(&self, operations: Vec<Operation>) -> anyhow::Result<()> {
+        if let Some(journal) = &self.journal {
+            journal
+                .append(Action::Do, operations)
+                .context("Unable to record the change in the journal")?;
+        }
+
+        Ok(())
+    }
+
+    fn journal(&self) -> anyhow::Result<&Journal> {
+        self.journal
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("There is no journal of changes"))
+    }
+
+    fn undo(&mut self) -> anyhow::Result<()> {
+        let operations = self.journal()?.undo(self.repo.as_ref())?;
+        self.write(&format!("Undid:\n{}", commands::operations(&operations)))
+    }
+
+    fn redo(&mut self) -> anyhow::Result<()> {
+        let operations = self.journal()?.redo(self.repo.as_ref())?;
+        self.write(&format!("Redid:\n{}", commands::operations(&operations)))
+    }
+
+    fn log(&mut self, count: usize) -> anyhow::Result<()> {
+        let entries = self.journal()?.entries()?;
+        let output = commands::log(&entries, count, self.zone());
+        self.write(&output)
+    }
+
+    /// Start tracking time for a task. Time is tracked for one task at a time, so any
+    /// other task is stopped first.
+    fn start(&mut self, id: &str) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let tasks = self.repo.all()?;
+        let task = cli::find(id, &tasks)?.clone().with_started(now)?;
+
+        let mut operations = Vec::new();
+        for running in tasks.iter().filter(|x| x.is_running()) {
+            operations.extend(self.update(running.clone().with_stopped(now)?)?);
+        }
+        let name = task.name().to_owned();
+        operations.extend(self.update(task)?);
+        self.record(operations)?;
+
+        self.write(&format!("Started '{name}'"))
+    }
+
+    fn stop(&mut self) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let tasks = self.repo.all()?;
+        let running = tasks.iter().filter(|x| x.is_running()).collect::<Vec<_>>();
+        if running.is_empty() {
+            anyhow::bail!("No task is being tracked");
+        }
+
+        let mut operations = Vec::new();
+        let mut lines = Vec::new();
+        for task in running {
+            let task = task.clone().with_stopped(now)?;
+            let spent = task
+                .time_entries()
+                .last()
+                .map_or_else(chrono::Duration::zero, |x| {
+                    x.duration_between(x.start, now, now)
+                });
+            lines.push(format!(
+                "Stopped '{}' after {}",
+                task.name(),
+                format_duration(spent)
+            ));
+            operations.extend(self.update(task)?);
+        }
+        self.record(operations)?;
+
+        self.write(&lines.join("\n"))
+    }
+
+    fn report(&mut self, period: Period) -> anyhow::Result<()> {
+        let tasks = self.repo.all()?;
+        let output = commands::report(&tasks, period, Utc::now(), self.zone());
+        self.write(&output)
+    }
+
+    fn write(&mut self, output: &str) -> anyhow::Result<()> {
+        if let Some(ref mut writer) = self.writer {
+            writer.write(output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the programs on the tasks and save the changes they make. A program that fails
+    /// is skipped, unless `options` says that either all or none of them are to be run.
+    /// `parse_errors` is how many lines of the input failed to parse into programs.
+    fn edit(
+        &mut self,
+        programs: Vec<Program>,
+        parse_errors: usize,
+        options: EditOptions,
+    ) -> anyhow::Result<()> {
+        let mut tasks = self.repo.all()?;
+        let before = tasks.as_slice().to_vec();
+        let (operations, errors) = self.run_programs(programs, &mut tasks);
+        for error in &errors {
+            eprintln!("{error:#}");
+        }
+        let failed = parse_errors + errors.len();
+
+        if options.dry_run {
+            let formatter = DetailFormatter::new()
+                .with_tasks(&before)
+                .with_date_format(&self.config.list_layout.value().date_format)
+                .with_timezone(self.zone());
+            let output = commands::dry_run(&operations, self.zone(), &formatter);
+            self.write(&output)?;
+        } else if options.atomic && failed > 0 {
+            anyhow::bail!("{failed} of the lines failed, nothing was changed");
+        } else {
+            self.repo.write(&operations)?;
+            self.record(operations)?;
+        }
+
+        if failed > 0 {
+            anyhow::bail!("{failed} of the lines failed");
+        }
+        Ok(())
+    }
+
+    /// Run the programs in the script file at `path`.
+    fn edit_file(&mut self, path: &Path, options: EditOptions) -> anyhow::Result<()> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read the script '{}'", path.display()))?;
+        let (programs, parse_errors) = commands::script(&text, self.zone());
+
+        self.edit(programs, parse_errors, options)
+    }
+
+    /// Write the tasks that aren't completed to a file, let the user change it in an editor
+    /// and make the changes in it.
+    fn edit_in_editor(&mut self, options: EditOptions) -> anyhow::Result<()> {
+        let zone = self.zone();
+        let tasks = self.repo.all()?;
+        let id_length = commands::shortest_id_length(&tasks).max(5);
+        let open = tasks
+            .iter()
+            .filter(|x| !x.is_completed())
+            .collect::<Vec<_>>();
+
+        let text = commands::edit_file(&open, id_length, zone);
+        let edited = ui::edit_text(&text, ".txt")?;
+        let programs = commands::edit_diff(&open, &edited, zone)?;
+
+        self.edit(programs, 0, options)
+    }
+
+    /// Run the programs on `tasks` one by one and return the operations they made. The
+    /// programs that fail are skipped and their errors returned.
+    fn run_programs(
+        &self,
+        programs: Vec<Program>,
+        tasks: &mut TaskList,
+    ) -> (Vec<Operation>, Vec<anyhow::Error>) {
+        let mut operations = Vec::new();
+        let mut errors = Vec::new();
 
         for program in programs {
-            match program {
-                Program::Edit { id, name, due } => {
-                    let filtered_tasks = tasks
-                        .iter()
-                        .filter(|x| x.id().to_string().starts_with(&id))
-                        .collect::<Vec<_>>();
-
-                    match filtered_tasks.len() {
-                        0 => eprintln!("No task found with the id '{}'", id),
-                        1 => {
-                            let new_task = filtered_tasks[0].clone().with_name(name).with_due(due);
-                            if let Err(e) = tasks.edit(new_task) {
-                                eprintln!("Unable to edit the task: {e}");
-                            }
-                        }
-                        _ => {
-                            eprintln!("More than one possible task was found with the id '{}'", id)
-                        }
-                    }
-                }
-                Program::Add(task) => tasks.add(task),
-                Program::Remove(partial_id) => cli::remove(&partial_id, &mut tasks)?,
-                _ => {}
+            let id = program.id().map(str::to_owned);
+            match self.run_program(program, tasks) {
+                Ok(x) => operations.extend(x),
+                Err(e) => errors.push(match id {
+                    Some(id) => e.context(format!("Unable to change the task '{id}'")),
+                    None => e.context("Unable to add the task"),
+                }),
             }
         }
 
-        Ok(self.repo.save(tasks)?)
+        (operations, errors)
+    }
+
+    fn run_program(
+        &self,
+        program: Program,
+        tasks: &mut TaskList,
+    ) -> anyhow::Result<Vec<Operation>> {
+        let operations = match program {
+            Program::Edit { id, name, due } => {
+                let task = cli::find(&id, tasks)?.clone().with_name(name).with_due(due);
+                edit(tasks, task)?
+            }
+            Program::Add(task) => {
+                let id = task.id().to_string();
+                tasks.add(task);
+                vec![Operation::Add(cli::find(&id, tasks)?.clone())]
+            }
+            Program::Remove(id) => {
+                let task = cli::find(&id, tasks)?.clone();
+                tasks.remove(task.id());
+                vec![Operation::Remove(task)]
+            }
+            Program::Done(id) => {
+                let (task, next) = cli::done(&id, tasks, self.zone())?;
+                let mut operations = edit(tasks, task)?;
+                if let Some(next) = next {
+                    let id = next.id().to_string();
+                    tasks.add(next);
+                    operations.push(Operation::Add(cli::find(&id, tasks)?.clone()));
+                }
+                operations
+            }
+            Program::Repeat { id, repeat } => {
+                let task = cli::find(&id, tasks)?.clone().with_repeat(repeat);
+                edit(tasks, task)?
+            }
+            Program::Priority { id, priority } => {
+                let task = cli::find(&id, tasks)?.clone().with_priority(priority);
+                edit(tasks, task)?
+            }
+            Program::AddItem { id, name } => {
+                let task = cli::find(&id, tasks)?
+                    .clone()
+                    .with_item(ChecklistItem::new(name));
+                edit(tasks, task)?
+            }
+            Program::Depend { id, on } => {
+                let id = cli::find(&id, tasks)?.id().clone();
+                let on = cli::find(&on, tasks)?.id().clone();
+                let old = tasks.add_dependency(&id, &on)?;
+                let new = cli::find(&id.to_string(), tasks)?.clone();
+                vec![Operation::Edit { old, new }]
+            }
+            Program::Undepend { id, on } => {
+                let task = cli::find(&id, tasks)?;
+                // The task waited for may have been removed, so only the ids are searched
+                let dependency = task
+                    .depends_on()
+                    .iter()
+                    .find(|x| x.to_string().starts_with(&on))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "'{}' doesn't wait for a task with the id '{on}'",
+                            task.name()
+                        )
+                    })?;
+                let task = task.clone().without_dependency(&dependency.clone());
+                edit(tasks, task)?
+            }
+            Program::Estimate { id, estimate } => {
+                let task = cli::find(&id, tasks)?.clone().with_estimate(estimate);
+                edit(tasks, task)?
+            }
+            Program::Update {
+                id,
+                name,
+                due,
+                add_tags,
+                remove_tags,
+            } => {
+                let mut task = cli::find(&id, tasks)?.clone();
+                if let Some(name) = name {
+                    task = task.with_name(name);
+                }
+                if let Some(due) = due {
+                    task = task.with_due(due);
+                }
+                let task = task.with_tags(add_tags).without_tags(&remove_tags);
+                edit(tasks, task)?
+            }
+            Program::Tick { id, item } => {
+                let task = cli::find(&id, tasks)?.clone().with_ticked(item)?;
+                edit(tasks, task)?
+            }
+            Program::Empty => Vec::new(),
+        };
+
+        Ok(operations)
+    }
+
+    /// Replace a task and return the operation, if there was a task to replace.
+    fn update(&self, task: Task) -> anyhow::Result<Option<Operation>> {
+        let old = self.repo.update(task.clone())?;
+        Ok(old.map(|old| Operation::Edit { old, new: task }))
     }
 
     fn interactive(&self) -> anyhow::Result<()> {
-        let mut tasks = TaskList::from(self.repo.all()?);
-        let mut formatter = TodayFormatter::new();
+        let zone = self.zone();
+        let mut tasks = self.repo.all()?;
+        let before = tasks.as_slice().to_vec();
+        let mut formatter = TodayFormatter::new().with_timezone(zone);
         formatter.insert(
             Field::Id,
             Cell::default().with_visibility(Visibility::Hidden),
         );
         formatter.insert(Field::Name, Cell::default().with_margin((0, 1)));
 
-        let mut formatter = ListFormatter::new();
+        let mut formatter = ListFormatter::new().with_timezone(zone);
 
         let default_cell = Cell::default().with_margin((0, 1));
         formatter.insert(
@@ -242,14 +711,18 @@ impl App {
             default_cell.clone().with_visibility(Visibility::Hidden),
         );
         formatter.insert(Field::Name, default_cell.clone());
+        formatter.insert(Field::Priority, priority_cell(&tasks, default_cell.clone()));
+        formatter.insert(Field::Progress, progress_cell(&tasks, default_cell.clone()));
+        formatter.insert(Field::Tags, tags_cell(&tasks, default_cell.clone()));
         formatter.insert(Field::Time, default_cell.clone());
+        let show_completed = self.show_completed();
 
         loop {
             let option = ui::menu()?;
 
             match option {
                 ui::MenuOption::Quit => break,
-                ui::MenuOption::Add => commands::add(ui::prompt_task, &mut tasks)?,
+                ui::MenuOption::Add => commands::add(|| ui::prompt_task(zone), &mut tasks)?,
                 ui::MenuOption::List => {
                     let max_name_length = tasks
                         .iter()
@@ -264,13 +737,84 @@ impl App {
                         .with_size(formatter::Size::Max(max_name_length))
                         .into();
 
-                    println!("{}", commands::list(tasks.as_slice(), &formatter))
+                    let listed = tasks.iter().filter(|x| show_completed || !x.is_completed());
+                    println!("{}", commands::list(listed, &formatter))
                 }
                 ui::MenuOption::Remove => commands::remove(ui::prompt_task_remove, &mut tasks)?,
-                ui::MenuOption::Today => println!("{}", commands::list(tasks.today(), &formatter)),
+                ui::MenuOption::Today => println!(
+                    "{}",
+                    commands::list(tasks.today().with_timezone(zone), &formatter)
+                ),
             }
         }
 
-        Ok(self.repo.save(tasks)?)
+        let operations = journal::diff(&before, &tasks);
+        self.repo.save(tasks)?;
+        self.record(operations)
+    }
+}
+
+/// Replace a task in `tasks` and return the operation, unless nothing changed.
+fn edit(tasks: &mut TaskList, task: Task) -> anyhow::Result<Vec<Operation>> {
+    let id = task.id().to_string();
+    let old = tasks.edit(task)?;
+    let new = cli::find(&id, tasks)?.clone();
+
+    Ok(if old == new {
+        Vec::new()
+    } else {
+        vec![Operation::Edit { old, new }]
+    })
+}
+
+/// A cell wide enough for the longest checklist progress in `tasks`. The column is hidden
+/// if none of the tasks have a checklist.
+fn progress_cell(tasks: &TaskList, cell: Cell) -> Cell {
+    let width = tasks
+        .iter()
+        .filter_map(Task::progress)
+        .map(|(done, total)| format!("{done}/{total}").len())
+        .max();
+
+    match width {
+        Some(width) => cell.with_size(formatter::Size::Min(width)),
+        None => cell.with_visibility(Visibility::Hidden),
+    }
+}
+
+/// A cell wide enough for the longest tags in `tasks`. The column is hidden if none of the
+/// tasks have tags.
+fn tags_cell(tasks: &TaskList, cell: Cell) -> Cell {
+    let width = tasks
+        .iter()
+        .map(Task::tags)
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            x.iter()
+                .map(Tag::as_str)
+                .collect::<Vec<_>>()
+                .join(" ")
+                .len()
+        })
+        .max();
+
+    match width {
+        Some(width) => cell.with_size(formatter::Size::Min(width)),
+        None => cell.with_visibility(Visibility::Hidden),
+    }
+}
+
+/// A cell wide enough for the longest priority in `tasks`. The column is hidden
+/// if none of the tasks have a priority.
+fn priority_cell(tasks: &TaskList, cell: Cell) -> Cell {
+    let width = tasks
+        .iter()
+        .filter(|x| x.priority() != Priority::None)
+        .map(|x| x.priority().to_string().len())
+        .max();
+
+    match width {
+        Some(width) => cell.with_size(formatter::Size::Min(width)),
+        None => cell.with_visibility(Visibility::Hidden),
     }
 }
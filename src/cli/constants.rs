@@ -3,11 +3,37 @@ pub const ARG_COMMAND_LIST: &str = "list";
 pub const ARG_COMMAND_REMOVE: &str = "remove";
 pub const ARG_COMMAND_EDIT: &str = "edit";
 pub const ARG_COMMAND_ADD: &str = "add";
+pub const ARG_COMMAND_DONE: &str = "done";
+pub const ARG_COMMAND_SHOW: &str = "show";
+pub const ARG_COMMAND_NOTE: &str = "note";
+pub const ARG_COMMAND_UNDO: &str = "undo";
+pub const ARG_COMMAND_REDO: &str = "redo";
+pub const ARG_COMMAND_LOG: &str = "log";
+pub const ARG_COMMAND_START: &str = "start";
+pub const ARG_COMMAND_STOP: &str = "stop";
+pub const ARG_COMMAND_REPORT: &str = "report";
 
 pub const ARG_WATCH_MODE: &str = "watch";
 pub const ARG_CONFIG: &str = "config";
+pub const ARG_SHOW_COMPLETED: &str = "all";
+pub const ARG_BLOCKED: &str = "blocked";
+pub const ARG_READY: &str = "ready";
 
 pub const ARG_DUE: &str = "due";
 pub const ARG_NOW: &str = "now";
 pub const ARG_NAME: &str = "name";
+pub const ARG_REPEAT: &str = "repeat";
+pub const ARG_PRIORITY: &str = "priority";
+pub const ARG_TAG: &str = "tag";
+pub const ARG_PROJECT: &str = "project";
+pub const ARG_QUERY: &str = "query";
+pub const ARG_TIMEZONE: &str = "timezone";
 pub const ARG_ID: &str = "id";
+pub const ARG_COUNT: &str = "count";
+pub const ARG_ESTIMATE: &str = "estimate";
+pub const ARG_DAY: &str = "day";
+pub const ARG_WEEK: &str = "week";
+pub const ARG_FORMAT: &str = "format";
+pub const ARG_DRY_RUN: &str = "dry-run";
+pub const ARG_ATOMIC: &str = "atomic";
+pub const ARG_FILE: &str = "file";
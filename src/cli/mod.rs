@@ -1,7 +1,16 @@
+use std::path::PathBuf;
+
 use chrono::prelude::*;
-use clap::{command, Arg, ArgMatches, Command};
+use clap::{command, Arg, ArgAction, ArgMatches, Command};
 
-use today::{Task, TaskList, TaskName};
+use today::{
+    parser::{date, query::Query},
+    recurrence::Recurrence,
+    task::{Priority, Tag},
+    timezone::Zone,
+    tracking::Minutes,
+    Task, TaskList, TaskName,
+};
 
 mod constants;
 pub use constants::*;
@@ -12,15 +21,33 @@ pub fn options() -> ArgMatches {
         .args(&[Arg::new(ARG_CONFIG)
             .long("config-only")
             .help("Print only the configuration of this run but don't run it")])
-        .subcommand(Command::new(ARG_COMMAND_LIST).about("List all tasks"))
+        .subcommand(
+            Command::new(ARG_COMMAND_LIST)
+                .arg(show_completed())
+                .args(&[
+                    Arg::new(ARG_BLOCKED)
+                        .long("blocked")
+                        .conflicts_with(ARG_READY)
+                        .help("Only show tasks waiting for other tasks to be done"),
+                    Arg::new(ARG_READY)
+                        .long("ready")
+                        .help("Only show tasks that don't wait for other tasks"),
+                    output_format(),
+                ])
+                .args(filters())
+                .about("List all tasks"),
+        )
         .subcommand(
             Command::new(ARG_COMMAND_TODAY)
-                .arg(
+                .args(&[
                     Arg::new(ARG_WATCH_MODE)
                         .short('w')
                         .long("watch")
                         .help("Run in watch mode"),
-                )
+                    show_completed(),
+                    output_format(),
+                ])
+                .args(filters())
                 .about("List tasks that are due today"),
         )
         .subcommand(
@@ -33,6 +60,36 @@ pub fn options() -> ArgMatches {
                 )
                 .about("Removes a task"),
         )
+        .subcommand(
+            Command::new(ARG_COMMAND_DONE)
+                .arg(
+                    Arg::new(ARG_ID)
+                        .required(true)
+                        .value_name("ID")
+                        .help("The id of the task to mark as completed"),
+                )
+                .about("Mark a task as completed"),
+        )
+        .subcommand(
+            Command::new(ARG_COMMAND_SHOW)
+                .arg(
+                    Arg::new(ARG_ID)
+                        .required(true)
+                        .value_name("ID")
+                        .help("The id of the task to show"),
+                )
+                .about("Show a task and its history"),
+        )
+        .subcommand(
+            Command::new(ARG_COMMAND_NOTE)
+                .arg(
+                    Arg::new(ARG_ID)
+                        .required(true)
+                        .value_name("ID")
+                        .help("The id of the task to write notes for"),
+                )
+                .about("Edit the notes of a task in $EDITOR"),
+        )
         .subcommand(
             Command::new(ARG_COMMAND_ADD)
                 .args(&[
@@ -46,12 +103,50 @@ pub fn options() -> ArgMatches {
                         .long("due")
                         .takes_value(true)
                         .value_parser(clap::builder::ValueParser::new(|x: &str| {
-                            NaiveDateTime::parse_from_str(x, "%Y-%m-%d %H:%M")
-                                .or(NaiveDate::parse_from_str(x, "%Y-%m-%d")
-                                    .map(|x| x.and_hms(0, 0, 0)))
-                                .map(|x| Utc.from_local_datetime(&x).unwrap())
+                            date::parse(x, Utc::now().naive_utc()).map(|_| x.to_owned())
+                        }))
+                        .help("Set the due date, e.g. 'YYYY-MM-DD HH:MM', 'tomorrow 9:00', 'next friday' or '+2w'"),
+                    Arg::new(ARG_TIMEZONE)
+                        .long("timezone")
+                        .takes_value(true)
+                        .value_name("ZONE")
+                        .value_parser(clap::builder::ValueParser::new(|x: &str| x.parse::<Zone>()))
+                        .help("Keep the task in a time zone of its own, e.g. 'America/New_York'"),
+                    Arg::new(ARG_REPEAT)
+                        .short('r')
+                        .long("repeat")
+                        .takes_value(true)
+                        .value_name("RULE")
+                        .value_parser(clap::builder::ValueParser::new(|x: &str| {
+                            x.parse::<Recurrence>()
+                        }))
+                        .help("Repeat the task: daily, weekly:mon,fri, monthly:15 or every:3"),
+                    Arg::new(ARG_PRIORITY)
+                        .short('p')
+                        .long("priority")
+                        .takes_value(true)
+                        .value_name("PRIORITY")
+                        .value_parser(clap::builder::ValueParser::new(|x: &str| {
+                            x.parse::<Priority>()
+                        }))
+                        .help("Set the priority: none, low, medium or high"),
+                    Arg::new(ARG_TAG)
+                        .short('t')
+                        .long("tag")
+                        .takes_value(true)
+                        .action(ArgAction::Append)
+                        .value_name("TAG")
+                        .value_parser(clap::builder::ValueParser::new(|x: &str| x.parse::<Tag>()))
+                        .help("Add a +project or @context tag, can be given multiple times"),
+                    Arg::new(ARG_ESTIMATE)
+                        .short('e')
+                        .long("estimate")
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .value_parser(clap::builder::ValueParser::new(|x: &str| {
+                            x.parse::<Minutes>()
                         }))
-                        .help("Set the due date in the format YYYY-MM-DD HH:MM"),
+                        .help("Set how long the task is expected to take, e.g. '1h30m' or '45m'"),
                     Arg::new(ARG_NAME)
                         .required(false)
                         .value_name("NAME")
@@ -66,23 +161,131 @@ pub fn options() -> ArgMatches {
                 ])
                 .about("Add a new task"),
         )
-        .subcommand(Command::new(ARG_COMMAND_EDIT).about("Edit one or more tasks"))
+        .subcommand(
+            Command::new(ARG_COMMAND_EDIT)
+                .args(&[
+                    Arg::new(ARG_DRY_RUN)
+                        .long("dry-run")
+                        .help("Show what would change without changing anything"),
+                    Arg::new(ARG_ATOMIC)
+                        .long("atomic")
+                        .help("Change nothing if any of the lines fail"),
+                    Arg::new(ARG_FILE)
+                        .short('f')
+                        .long("file")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Read the changes from a script file instead of stdin"),
+                ])
+                .about("Edit one or more tasks, in $EDITOR if nothing is piped to it"),
+        )
+        .subcommand(Command::new(ARG_COMMAND_UNDO).about("Undo the last change"))
+        .subcommand(Command::new(ARG_COMMAND_REDO).about("Redo the last undone change"))
+        .subcommand(
+            Command::new(ARG_COMMAND_LOG)
+                .arg(
+                    Arg::new(ARG_COUNT)
+                        .short('n')
+                        .long("count")
+                        .takes_value(true)
+                        .value_name("COUNT")
+                        .default_value("10")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("How many changes to show"),
+                )
+                .about("List the most recent changes"),
+        )
+        .subcommand(
+            Command::new(ARG_COMMAND_START)
+                .arg(
+                    Arg::new(ARG_ID)
+                        .required(true)
+                        .value_name("ID")
+                        .help("The id of the task to track time for"),
+                )
+                .about("Start tracking time for a task, stopping any other task"),
+        )
+        .subcommand(Command::new(ARG_COMMAND_STOP).about("Stop tracking time"))
+        .subcommand(
+            Command::new(ARG_COMMAND_REPORT)
+                .args(&[
+                    Arg::new(ARG_DAY)
+                        .long("day")
+                        .conflicts_with(ARG_WEEK)
+                        .help("Summarize the time tracked today"),
+                    Arg::new(ARG_WEEK)
+                        .long("week")
+                        .help("Summarize the time tracked this week, the default"),
+                ])
+                .about("Summarize the tracked time by task and tag"),
+        )
         .get_matches()
 }
 
-pub fn add(name: TaskName, due: Option<DateTime<Utc>>, tasks: &mut TaskList) -> anyhow::Result<()> {
+fn show_completed<'a>() -> Arg<'a> {
+    Arg::new(ARG_SHOW_COMPLETED)
+        .short('a')
+        .long("all")
+        .help("Show completed tasks as well")
+}
+
+fn output_format<'a>() -> Arg<'a> {
+    Arg::new(ARG_FORMAT)
+        .long("format")
+        .takes_value(true)
+        .value_name("FORMAT")
+        .value_parser(["text", "json", "ndjson"])
+        .help("Write the tasks as text, as a JSON array or as one JSON object per line")
+}
+
+fn filters<'a>() -> [Arg<'a>; 3] {
+    [
+        Arg::new(ARG_QUERY)
+            .required(false)
+            .value_name("QUERY")
+            .value_parser(clap::builder::ValueParser::new(|x: &str| x.parse::<Query>()))
+            .help("Only show tasks matching the query, e.g. 'due:<2022-11-01 and tag:work and not done'"),
+        Arg::new(ARG_TAG)
+            .short('t')
+            .long("tag")
+            .takes_value(true)
+            .action(ArgAction::Append)
+            .value_name("TAG")
+            .help("Only show tasks with this tag, with or without its prefix"),
+        Arg::new(ARG_PROJECT)
+            .short('p')
+            .long("project")
+            .takes_value(true)
+            .action(ArgAction::Append)
+            .value_name("PROJECT")
+            .help("Only show tasks in this +project"),
+    ]
+}
+
+pub fn add(
+    name: TaskName,
+    due: Option<DateTime<Utc>>,
+    repeat: Option<Recurrence>,
+    priority: Priority,
+    tags: Vec<Tag>,
+    timezone: Option<Zone>,
+    estimate: Option<Minutes>,
+) -> Task {
     let task = if let Some(date) = due {
         Task::new(name).with_date_time(date)
     } else {
         Task::new(name)
     };
-
-    tasks.add(task);
-
-    Ok(())
+    task.with_repeat(repeat)
+        .with_priority(priority)
+        .with_tags(tags)
+        .with_timezone(timezone)
+        .with_estimate(estimate)
 }
 
-pub fn remove(id: &str, tasks: &mut TaskList) -> anyhow::Result<()> {
+/// Find the only task whose id starts with `id`.
+pub fn find<'a>(id: &str, tasks: &'a TaskList) -> anyhow::Result<&'a Task> {
     let filtered_tasks = tasks
         .iter()
         .filter(|x| {
@@ -90,17 +293,51 @@ pub fn remove(id: &str, tasks: &mut TaskList) -> anyhow::Result<()> {
 
             task_id.starts_with(id)
         })
-        .cloned()
         .collect::<Vec<_>>();
 
     match filtered_tasks.len() {
         0 => anyhow::bail!("No task found with that id"),
-        1 => {
-            let id = filtered_tasks[0].id();
-            tasks.remove(id);
-        }
+        1 => Ok(filtered_tasks[0]),
         _ => anyhow::bail!("More than one possible task was found with that id"),
-    };
+    }
+}
+
+/// Mark a task as completed. Returns the completed task and, for a repeating task, its
+/// next occurrence. Fails given a task that is already completed.
+pub fn done(id: &str, tasks: &TaskList, zone: Zone) -> anyhow::Result<(Task, Option<Task>)> {
+    let now = Utc::now();
+    let task = find(id, tasks)?;
+    if task.is_completed() {
+        anyhow::bail!("'{}' is already completed", task.name());
+    }
+
+    let task = task.clone().with_completed(Some(now));
+    let next = task.next_occurrence(now, zone);
+
+    Ok((task, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn done_should_fail_given_completed_task() {
+        let zone = Zone::Named(chrono_tz::UTC);
+        let task = Task::new(TaskName::new("Water plants").unwrap())
+            .with_due(Some(Utc::now()))
+            .with_repeat(Some(Recurrence::Daily));
+        let id = task.id().to_string();
+        let mut tasks = TaskList::new();
+        tasks.add(task);
+
+        let (completed, next) = done(&id, &tasks, zone).unwrap();
+        assert!(next.is_some());
+        tasks.remove(completed.id());
+        tasks.add(completed.clone());
 
-    Ok(())
+        let error = done(&id, &tasks, zone).unwrap_err();
+        assert_eq!(error.to_string(), "'Water plants' is already completed");
+        assert_eq!(find(&id, &tasks).unwrap(), &completed);
+    }
 }
@@ -1,7 +1,19 @@
-use chrono::prelude::*;
+use std::{collections::BTreeMap, path::PathBuf};
+
+use chrono::{prelude::*, Duration};
 use itertools::Itertools;
 
-use today::{formatter::TaskFormatter, parser::program::Program, Task, TaskList, TaskName};
+use today::{
+    formatter::{DetailFormatter, TaskFormatter},
+    history::{self, ChangeKind},
+    journal::{Entry, Operation},
+    parser::program::{Parser, Program, Script},
+    recurrence::Recurrence,
+    task::{Priority, Tag},
+    timezone::Zone,
+    tracking::{format_duration, Minutes},
+    Task, TaskList, TaskName,
+};
 
 pub mod parser;
 
@@ -11,17 +23,86 @@ pub enum Command {
     Add {
         name: Option<TaskName>,
         due: Option<Option<DateTime<Utc>>>,
+        repeat: Option<Recurrence>,
+        priority: Option<Priority>,
+        tags: Vec<Tag>,
+        timezone: Option<Zone>,
+        estimate: Option<Minutes>,
     },
     List,
     Remove(String),
+    Done(String),
+    Show(String),
+    Note(String),
     Today,
     Edit {
         program: Vec<Program>,
+        parse_errors: usize,
+        options: EditOptions,
     },
+    /// Edit the tasks in `$EDITOR`.
+    EditInEditor(EditOptions),
+    /// Run the programs in a script file.
+    EditFile(PathBuf, EditOptions),
+    Undo,
+    Redo,
+    Log(usize),
+    Start(String),
+    Stop,
+    Report(Period),
     #[default]
     Interactive,
 }
 
+/// How the programs of `today edit` are run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EditOptions {
+    /// Only show what would change
+    pub dry_run: bool,
+    /// Change nothing if any of the programs fail
+    pub atomic: bool,
+}
+
+/// How tasks are written: as text for people to read, as a JSON array or as one JSON
+/// object per line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+}
+
+/// Which tasks to list, depending on whether they wait for other tasks to be done.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    #[default]
+    All,
+    Blocked,
+    Ready,
+}
+
+/// The period of time a report covers, up to now.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    #[default]
+    Week,
+}
+
+impl Period {
+    /// The wall clock time the period that `now` is in starts, weeks start on Monday.
+    pub fn start(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let today = now.date();
+        let start = match self {
+            Period::Day => today,
+            Period::Week => today - Duration::days(today.weekday().num_days_from_monday().into()),
+        };
+
+        start.and_hms(0, 0, 0)
+    }
+}
+
 pub fn add<F>(input: F, tasks: &mut TaskList) -> anyhow::Result<()>
 where
     F: Fn() -> anyhow::Result<Task>,
@@ -54,16 +135,279 @@ where
 {
     let output = tasks
         .into_iter()
-        .sorted_by(|x, y| x.due().cmp(&y.due()))
+        .sorted_by(|x, y| {
+            y.priority()
+                .cmp(&x.priority())
+                .then_with(|| x.due().cmp(&y.due()))
+        })
         .map(|x| f.format(x))
         .collect::<Vec<_>>();
 
     output.join("\n")
 }
 
+/// The same tasks in the same order as [`list`], as a JSON array. `f` has to format each
+/// task on a single line.
+pub fn json<'a, T, I>(tasks: I, f: &T) -> String
+where
+    T: TaskFormatter,
+    I: IntoIterator<Item = &'a Task>,
+{
+    format!("[{}]", list(tasks, f).lines().join(","))
+}
+
+/// The last `count` journal entries, most recent first, with their time in `zone`.
+pub fn log(entries: &[Entry], count: usize, zone: Zone) -> String {
+    entries
+        .iter()
+        .rev()
+        .take(count)
+        .flat_map(|entry| {
+            let time = zone.from_utc(&entry.time).format("%Y-%m-%d %H:%M");
+            let header = format!("{time} {:<4}", entry.action.to_string());
+            let indent = " ".repeat(header.len());
+            entry
+                .operations
+                .iter()
+                .enumerate()
+                .map(move |(i, operation)| match i {
+                    0 => format!("{header} {operation}"),
+                    _ => format!("{indent} {operation}"),
+                })
+        })
+        .join("\n")
+}
+
+/// The total estimate of `tasks` and how much of it is left, if any of them have an estimate.
+pub fn estimates(tasks: &[&Task], now: DateTime<Utc>) -> Option<String> {
+    if tasks.iter().all(|x| x.estimate().is_none()) {
+        return None;
+    }
+
+    let estimated = tasks
+        .iter()
+        .filter_map(|x| x.estimate())
+        .fold(Duration::zero(), |sum, x| sum + x.to_duration());
+    let remaining = tasks
+        .iter()
+        .filter(|x| !x.is_completed())
+        .filter_map(|x| x.remaining(now))
+        .fold(Duration::zero(), |sum, x| sum + x);
+
+    Some(format!(
+        "Estimated {}, remaining {}",
+        format_duration(estimated),
+        format_duration(remaining)
+    ))
+}
+
+/// The time tracked for `tasks` during `period`, by task and by tag, longest first.
+pub fn report(tasks: &[Task], period: Period, now: DateTime<Utc>, zone: Zone) -> String {
+    let start = period.start(zone.from_utc(&now));
+    let from = zone.to_utc(&start);
+
+    let mut by_task = Vec::new();
+    let mut by_tag = BTreeMap::<Tag, Duration>::new();
+    for task in tasks {
+        let tracked = task.tracked(from, now, now);
+        if tracked.is_zero() {
+            continue;
+        }
+        let short_id = &task.id().to_string()[..5];
+        by_task.push((tracked, format!("{short_id} {}", task.name())));
+        for tag in task.tags() {
+            let sum = by_tag.entry(tag).or_insert_with(Duration::zero);
+            *sum = *sum + tracked;
+        }
+    }
+
+    let title = match period {
+        Period::Day => format!("Tracked on {}", start.format("%Y-%m-%d")),
+        Period::Week => format!("Tracked since {}", start.format("%Y-%m-%d")),
+    };
+    if by_task.is_empty() {
+        return format!("{title}\nNo time was tracked");
+    }
+
+    let total = by_task
+        .iter()
+        .fold(Duration::zero(), |sum, (x, _)| sum + *x);
+    let tags = by_tag
+        .into_iter()
+        .map(|(tag, x)| (x, tag.to_string()))
+        .collect::<Vec<_>>();
+    let width = by_task
+        .iter()
+        .chain(&tags)
+        .map(|(x, _)| format_duration(*x).len())
+        .max()
+        .unwrap_or_default();
+    let lines = |rows: Vec<(Duration, String)>| {
+        rows.into_iter()
+            .sorted_by(|x, y| y.0.cmp(&x.0).then_with(|| x.1.cmp(&y.1)))
+            .map(|(x, name)| format!("  {:<width$}  {name}", format_duration(x)))
+            .collect::<Vec<_>>()
+    };
+
+    let mut output = vec![title, String::from("Tasks:")];
+    output.extend(lines(by_task));
+    if !tags.is_empty() {
+        output.push(String::from("Tags:"));
+        output.extend(lines(tags));
+    }
+    output.push(format!("Total: {}", format_duration(total)));
+
+    output.join("\n")
+}
+
+/// The date format of due dates in the edit language.
+const EDIT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// `tasks` as lines of the edit language, `<id> <due> <name>`, to be edited all at once. The
+/// ids are shortened to `id_length` and due dates are written in `zone`.
+pub fn edit_file(tasks: &[&Task], id_length: usize, zone: Zone) -> String {
+    let header = [
+        "# Edit the tasks, one per line as <id> <due> <name>. The due date is",
+        "# 'YYYY-MM-DD HH:MM', 'Now' or a date like 'tomorrow 9:00'.",
+        "# Remove a line to remove the task, add 'new <due> <name>' for a new task.",
+        "# Any other line of 'today edit', like '<id> done', works as well.",
+        "# Lines starting with '#' are ignored.",
+    ];
+    let lines = tasks.iter().map(|task| {
+        let id = &task.id().to_string()[..id_length];
+        format!("{id} {} {}", edit_due(task.due(), zone), task.name())
+    });
+
+    header.iter().map(|x| x.to_string()).chain(lines).join("\n") + "\n"
+}
+
+fn edit_due(due: Option<&DateTime<Utc>>, zone: Zone) -> String {
+    due.map_or(String::from("Now"), |x| {
+        zone.from_utc(x).format(EDIT_DATE_FORMAT).to_string()
+    })
+}
+
+/// The programs of a script and how many lines failed to parse, the errors of all the lines
+/// are printed together.
+pub fn script(text: &str, zone: Zone) -> (Vec<Program>, usize) {
+    let (programs, errors) = Script::new(text).with_timezone(zone).parse();
+    for e in &errors {
+        eprintln!("{e}\n");
+    }
+
+    (programs, errors.len())
+}
+
+/// The programs that turn `tasks` into the edited `text` made from [`edit_file`]. Lines that
+/// are left as they were are skipped and the tasks that no longer have a line are removed.
+/// Nothing is returned if any of the lines can't be parsed or has an id that matches more than
+/// one task.
+pub fn edit_diff(tasks: &[&Task], text: &str, zone: Zone) -> anyhow::Result<Vec<Program>> {
+    let mut programs = Vec::new();
+    let mut errors = Vec::new();
+    let mut kept = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let program = match Parser::new(line)
+            .with_timezone(zone)
+            .with_line(number + 1)
+            .parse()
+        {
+            Ok(x) => x,
+            Err(e) => {
+                errors.push(e.to_string());
+                continue;
+            }
+        };
+
+        let id = program.id();
+        let matching = tasks
+            .iter()
+            .filter(|x| id.is_some_and(|id| x.id().to_string().starts_with(id)))
+            .collect::<Vec<_>>();
+        let task = match matching.as_slice() {
+            [] => None,
+            [task] => Some(**task),
+            _ => {
+                errors.push(format!(
+                    "line {}: More than one possible task was found with the id '{}'",
+                    number + 1,
+                    id.unwrap_or_default()
+                ));
+                continue;
+            }
+        };
+        if let Some(task) = task {
+            kept.push(task.id());
+        }
+        match (&program, task) {
+            (Program::Empty, _) => {}
+            (Program::Edit { name, due, .. }, Some(task))
+                if name.as_str() == task.name()
+                    && edit_due(due.as_ref(), zone) == edit_due(task.due(), zone) => {}
+            _ => programs.push(program),
+        }
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!("Nothing was changed\n\n{}", errors.join("\n\n"));
+    }
+    programs.extend(
+        tasks
+            .iter()
+            .filter(|x| !kept.contains(&x.id()))
+            .map(|x| Program::Remove(x.id().to_string())),
+    );
+
+    Ok(programs)
+}
+
+/// What `operations` would change, as the lines of the tasks before and after like a diff.
+/// Changes that can't be seen on the lines are described below them.
+pub fn dry_run(operations: &[Operation], zone: Zone, f: &DetailFormatter) -> String {
+    let line = |task: &Task| {
+        let id = &task.id().to_string()[..5];
+        format!("{id} {} {}", edit_due(task.due(), zone), task.name())
+    };
+
+    let mut output = Vec::new();
+    for operation in operations {
+        match operation {
+            Operation::Add(task) => output.push(format!("+ {}", line(task))),
+            Operation::Remove(task) => output.push(format!("- {}", line(task))),
+            Operation::Edit { old, new } => {
+                let changes = history::changes(old, new)
+                    .into_iter()
+                    .filter(|x| !matches!(x, ChangeKind::Renamed { .. } | ChangeKind::Due { .. }));
+                if line(old) == line(new) {
+                    output.push(format!("  {}", line(new)));
+                } else {
+                    output.push(format!("- {}", line(old)));
+                    output.push(format!("+ {}", line(new)));
+                }
+                output.extend(changes.map(|x| format!("    {}", f.change(new, &x))));
+            }
+        }
+    }
+
+    if output.is_empty() {
+        String::from("Nothing would change")
+    } else {
+        output.join("\n")
+    }
+}
+
+/// One line per operation, for telling what was changed.
+pub fn operations(operations: &[Operation]) -> String {
+    operations.iter().map(ToString::to_string).join("\n")
+}
+
 pub fn shortest_id_length(tasks: &[Task]) -> usize {
-    if tasks.is_empty() {
-        return 0;
+    if tasks.len() < 2 {
+        return tasks.len();
     }
 
     let ids = tasks
@@ -117,3 +461,176 @@ pub fn shortest_id_length(tasks: &[Task]) -> usize {
     let count = prefixes.iter().map(|x| x.len()).max().unwrap();
     count
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use today::formatter::JsonFormatter;
+
+    fn task(name: &str) -> Task {
+        Task::new(TaskName::new(name).unwrap())
+    }
+
+    fn utc() -> Zone {
+        "UTC".parse().unwrap()
+    }
+
+    #[test]
+    fn json_should_write_tasks_as_array_in_list_order() {
+        let tasks = vec![task("Later"), task("First").with_priority(Priority::High)];
+
+        let output = json(&tasks, &JsonFormatter::new());
+
+        let value = serde_json::from_str::<serde_json::Value>(&output).unwrap();
+        let names = value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["First", "Later"]);
+        assert_eq!(json(&[], &JsonFormatter::new()), "[]");
+    }
+
+    #[test]
+    fn edit_diff_should_turn_changed_lines_into_programs() {
+        let kept = task("Water plants").with_date_time(Utc.ymd(2022, 6, 15).and_hms(9, 0, 0));
+        let renamed = task("Call mom");
+        let removed = task("Old task");
+        let done = task("Pay rent");
+        let tasks = [&kept, &renamed, &removed, &done];
+        let text = edit_file(&tasks, 5, utc());
+        let short = |x: &Task| x.id().to_string()[..5].to_owned();
+
+        let edited = text
+            .replace("Call mom", "Call Mom and Dad")
+            .replace(&format!("{} Now Old task\n", short(&removed)), "")
+            .replace(
+                &format!("{} Now Pay rent", short(&done)),
+                &format!("{} done", short(&done)),
+            )
+            + "\n# A comment\nnew Now Buy milk\n";
+        let programs = edit_diff(&tasks, &edited, utc()).unwrap();
+
+        assert_eq!(programs.len(), 4);
+        assert_eq!(
+            programs[0],
+            Program::Edit {
+                id: short(&renamed),
+                name: TaskName::new("Call Mom and Dad").unwrap(),
+                due: None
+            }
+        );
+        assert_eq!(programs[1], Program::Done(short(&done)));
+        assert!(matches!(&programs[2], Program::Add(x) if x.name() == "Buy milk"));
+        assert_eq!(programs[3], Program::Remove(removed.id().to_string()));
+    }
+
+    #[test]
+    fn edit_diff_should_change_nothing_given_invalid_line() {
+        let task = task("Water plants");
+        let tasks = [&task];
+
+        let edited = "new sometime Plant a tree\n";
+
+        let error = edit_diff(&tasks, edited, utc()).unwrap_err();
+        assert!(error.to_string().contains("line 1, column"));
+    }
+
+    #[test]
+    fn edit_diff_should_change_nothing_given_ambiguous_id() {
+        let mut tasks = Vec::new();
+        let prefix = loop {
+            let task = task("Water plants");
+            let prefix = task.id().to_string()[..1].to_owned();
+            if tasks
+                .iter()
+                .any(|x: &Task| x.id().to_string().starts_with(&prefix))
+            {
+                tasks.push(task);
+                break prefix;
+            }
+            tasks.push(task);
+        };
+        let tasks = tasks.iter().collect::<Vec<_>>();
+        let text = edit_file(&tasks, 5, utc());
+
+        let edited = format!("{text}{prefix} done\n");
+
+        let error = edit_diff(&tasks, &edited, utc()).unwrap_err();
+        assert!(error.to_string().contains("More than one possible task"));
+    }
+
+    #[test]
+    fn dry_run_should_show_lines_before_and_after() {
+        let old = task("Call mom");
+        let renamed = old.clone().with_name(TaskName::new("Call dad").unwrap());
+        let prioritized = old.clone().with_priority(Priority::High);
+        let added = task("Buy milk").with_date_time(Utc.ymd(2022, 6, 15).and_hms(9, 0, 0));
+        let short = |x: &Task| x.id().to_string()[..5].to_owned();
+        let operations = [
+            Operation::Edit {
+                old: old.clone(),
+                new: renamed,
+            },
+            Operation::Edit {
+                old: old.clone(),
+                new: prioritized,
+            },
+            Operation::Add(added.clone()),
+            Operation::Remove(old.clone()),
+        ];
+
+        let output = dry_run(
+            &operations,
+            utc(),
+            &DetailFormatter::new().with_timezone(utc()),
+        );
+
+        let (id, new) = (short(&old), short(&added));
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            [
+                format!("- {id} Now Call mom"),
+                format!("+ {id} Now Call dad"),
+                format!("  {id} Now Call mom"),
+                String::from("    priority changed from none to high"),
+                format!("+ {new} 2022-06-15 09:00 Buy milk"),
+                format!("- {id} Now Call mom"),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_should_sum_tracked_time_this_week_by_task_and_tag() {
+        // A Wednesday
+        let at = |day, hour| Utc.ymd(2022, 6, day).and_hms(hour, 0, 0);
+        let tasks = vec![
+            task("Write docs +work")
+                .with_started(at(10, 9))
+                .unwrap()
+                .with_stopped(at(10, 11))
+                .unwrap()
+                .with_started(at(13, 9))
+                .unwrap()
+                .with_stopped(at(13, 10))
+                .unwrap(),
+            task("Review +work @office")
+                .with_started(at(15, 8))
+                .unwrap(),
+            task("Read a book"),
+        ];
+
+        let output = report(&tasks, Period::Week, at(15, 10), utc());
+
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "Tracked since 2022-06-13");
+        assert_eq!(lines[1], "Tasks:");
+        assert!(lines[2].starts_with("  2h  ") && lines[2].ends_with(" Review +work @office"));
+        assert!(lines[3].starts_with("  1h  ") && lines[3].ends_with(" Write docs +work"));
+        assert_eq!(
+            &lines[4..],
+            ["Tags:", "  3h  +work", "  2h  @office", "Total: 3h"]
+        );
+    }
+}
@@ -1,50 +1,114 @@
-use std::io;
+use std::{
+    io::{self, IsTerminal},
+    path::PathBuf,
+};
 
-use chrono::prelude::*;
 use clap::ArgMatches;
 
-use today::parser::program::{Parser, Program};
+use today::{
+    parser::{date, program::Program},
+    timezone::Zone,
+};
 
-use super::Command;
+use super::{Command, EditOptions, Period};
 use crate::cli;
 
-pub fn parse(command: &str, mut matches: ArgMatches) -> Option<Command> {
+/// Turn a subcommand into a [`Command`], dates given by the user are read as wall
+/// clock times in `zone`.
+pub fn parse(command: &str, mut matches: ArgMatches, zone: Zone) -> Option<Command> {
     match command {
-        "add" => Some(add(matches)),
+        "add" => Some(add(matches, zone)),
         "list" => Some(Command::List),
         "remove" => {
             let id = matches.remove_one::<String>(cli::ARG_ID).unwrap();
             Some(Command::Remove(id))
         }
+        "done" => {
+            let id = matches.remove_one::<String>(cli::ARG_ID).unwrap();
+            Some(Command::Done(id))
+        }
+        "show" => {
+            let id = matches.remove_one::<String>(cli::ARG_ID).unwrap();
+            Some(Command::Show(id))
+        }
+        "note" => {
+            let id = matches.remove_one::<String>(cli::ARG_ID).unwrap();
+            Some(Command::Note(id))
+        }
         "today" => Some(Command::Today),
-        "edit" => Some(Command::Edit { program: edit() }),
+        "undo" => Some(Command::Undo),
+        "redo" => Some(Command::Redo),
+        "log" => {
+            let count = matches.remove_one::<usize>(cli::ARG_COUNT).unwrap();
+            Some(Command::Log(count))
+        }
+        "start" => {
+            let id = matches.remove_one::<String>(cli::ARG_ID).unwrap();
+            Some(Command::Start(id))
+        }
+        "stop" => Some(Command::Stop),
+        "report" => {
+            let period = if matches.contains_id(cli::ARG_DAY) {
+                Period::Day
+            } else {
+                Period::Week
+            };
+            Some(Command::Report(period))
+        }
+        "edit" => {
+            let options = EditOptions {
+                dry_run: matches.contains_id(cli::ARG_DRY_RUN),
+                atomic: matches.contains_id(cli::ARG_ATOMIC),
+            };
+            if let Some(path) = matches.remove_one::<PathBuf>(cli::ARG_FILE) {
+                return Some(Command::EditFile(path, options));
+            }
+            // Without anything piped to it the tasks are edited in an editor instead
+            if io::stdin().is_terminal() {
+                return Some(Command::EditInEditor(options));
+            }
+
+            let (program, parse_errors) = edit(zone);
+            Some(Command::Edit {
+                program,
+                parse_errors,
+                options,
+            })
+        }
         _ => None,
     }
 }
 
-fn edit() -> Vec<Program> {
-    io::stdin()
-        .lines()
-        .map(|line| {
-            let line = line.unwrap();
-            let mut parser = Parser::new(&line);
-            parser.parse()
-        })
-        .inspect(|result| {
-            if let Err(e) = result {
-                eprintln!("Failed to parse: {e}");
-            }
-        })
-        .flatten()
-        .collect()
+/// The programs read from stdin and how many lines failed to parse.
+fn edit(zone: Zone) -> (Vec<Program>, usize) {
+    let text = io::read_to_string(io::stdin()).unwrap();
+    super::script(&text, zone)
 }
 
-fn add(mut matches: ArgMatches) -> Command {
+fn add(mut matches: ArgMatches, zone: Zone) -> Command {
     let name = matches.remove_one(cli::ARG_NAME);
+    let timezone = matches.remove_one::<Zone>(cli::ARG_TIMEZONE);
+    let zone = timezone.unwrap_or(zone);
     let now = matches.contains_id(cli::ARG_NOW).then_some(None);
     let due = matches
-        .remove_one::<DateTime<Utc>>(cli::ARG_DUE)
-        .map(|x| Some(x));
+        .remove_one::<String>(cli::ARG_DUE)
+        .and_then(|x| date::parse(&x, zone.now()).ok())
+        .map(|x| Some(zone.to_utc(&x)));
     let due = now.or(due);
-    Command::Add { name, due }
+    let repeat = matches.remove_one(cli::ARG_REPEAT);
+    let priority = matches.remove_one(cli::ARG_PRIORITY);
+    let tags = matches
+        .remove_many(cli::ARG_TAG)
+        .map(Iterator::collect)
+        .unwrap_or_default();
+    let estimate = matches.remove_one(cli::ARG_ESTIMATE);
+    Command::Add {
+        name,
+        due,
+        repeat,
+        priority,
+        tags,
+        timezone,
+        estimate,
+    }
 }
@@ -0,0 +1,193 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use today::{formatter::Layout, partial_config::Build, timezone::Zone};
+
+use crate::{commands::Command, AppPaths};
+
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Where the tasks are stored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// All tasks in a single `tasks.json`
+    #[default]
+    Json,
+    /// An SQLite database `tasks.db`, the tasks in `tasks.json` are imported into it
+    /// when it is first created
+    Sqlite,
+}
+
+/// The content of the configuration file found in the config directory.
+///
+/// ```toml
+/// data = "/home/user/.local/share/today"
+/// command = "today"
+/// watch = true
+/// show_completed = false
+/// timezone = "Europe/Stockholm"
+/// backups = 3
+/// backend = "sqlite"
+///
+/// [list]
+/// date_format = "%Y-%m-%d %H:%M"
+/// columns = ["id", "time", "name"]
+///
+/// [today]
+/// date_format = "%H:%M"
+/// columns = ["time", "name"]
+/// # Replaces the columns, see `Template` for the placeholders
+/// template = "{due!red:%H:%M|Now}: {?({priority}) }{name}"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    data: Option<PathBuf>,
+    command: Option<String>,
+    watch: Option<bool>,
+    show_completed: Option<bool>,
+    timezone: Option<Zone>,
+    backups: Option<usize>,
+    backend: Option<Backend>,
+    list: Option<Layout>,
+    today: Option<Layout>,
+}
+
+fn parse_command(command: &str) -> anyhow::Result<Command> {
+    match command {
+        "list" => Ok(Command::List),
+        "today" => Ok(Command::Today),
+        "interactive" => Ok(Command::Interactive),
+        _ => anyhow::bail!(
+            "Unknown default command '{command}', expected one of 'list', 'today' or 'interactive'"
+        ),
+    }
+}
+
+fn parse(content: &str) -> anyhow::Result<AppPaths<Build>> {
+    let file = toml::from_str::<ConfigFile>(content)?;
+    let command = file.command.as_deref().map(parse_command).transpose()?;
+
+    Ok(AppPaths {
+        data: file.data.into(),
+        command: command.into(),
+        watch_mode: file.watch.into(),
+        show_completed: file.show_completed.into(),
+        timezone: file.timezone.into(),
+        backups: file.backups.into(),
+        backend: file.backend.into(),
+        list_layout: file.list.into(),
+        today_layout: file.today.into(),
+        ..Default::default()
+    })
+}
+
+/// Read the configuration file in the directory `config`. A missing file is
+/// the same as an empty configuration.
+pub fn read_file(config: &Path) -> anyhow::Result<AppPaths<Build>> {
+    let path = config.join(CONFIG_FILE_NAME);
+    let content = match fs::read_to_string(&path) {
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Default::default()),
+        x => x,
+    }
+    .with_context(|| format!("Unable to read config file {}", path.to_string_lossy()))?;
+
+    parse(&content).with_context(|| format!("Invalid config file {}", path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use today::formatter::Field;
+
+    #[test]
+    fn parse_should_read_all_settings() {
+        let config = parse(
+            r#"
+            data = "/tmp/today"
+            command = "list"
+            watch = true
+            backups = 5
+            backend = "sqlite"
+
+            [today]
+            date_format = "%H:%M"
+            columns = ["time", "name"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.data.get().0, Some(PathBuf::from("/tmp/today")));
+        assert!(matches!(config.command.get().0, Some(Command::List)));
+        assert_eq!(config.watch_mode.get().0, Some(true));
+        assert_eq!(config.backups.get().0, Some(5));
+        assert_eq!(config.backend.get().0, Some(Backend::Sqlite));
+        assert_eq!(config.list_layout.get().0, None);
+        assert_eq!(
+            config.today_layout.get().0,
+            Some(Layout {
+                date_format: String::from("%H:%M"),
+                columns: vec![Field::Time, Field::Name],
+                template: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_should_read_template_per_command() {
+        let config = parse("[list]\ntemplate = \"{id:8} {due:%a %H:%M|ASAP} {name}\"").unwrap();
+
+        let layout = config.list_layout.get().0.unwrap();
+        assert_eq!(
+            layout.template.map(|x| x.to_string()),
+            Some(String::from("{id:8} {due:%a %H:%M|ASAP} {name}"))
+        );
+        assert_eq!(config.today_layout.get().0, None);
+        assert!(parse("[today]\ntemplate = \"{title}\"").is_err());
+    }
+
+    #[test]
+    fn parse_should_use_default_layout_for_missing_keys() {
+        let config = parse("[list]\ncolumns = [\"name\"]").unwrap();
+
+        let layout = config.list_layout.get().0.unwrap();
+        assert_eq!(layout.date_format, Layout::default().date_format);
+        assert_eq!(layout.columns, vec![Field::Name]);
+    }
+
+    #[test]
+    fn parse_should_fail_given_invalid_date_format() {
+        let error = parse("[list]\ndate_format = \"%Y-%Q\"").unwrap_err();
+
+        assert!(error.to_string().contains("invalid date format '%Y-%Q'"));
+    }
+
+    #[test]
+    fn parse_should_fail_given_unknown_command() {
+        assert!(parse("command = \"dance\"").is_err());
+    }
+
+    #[test]
+    fn parse_should_read_timezone() {
+        let config = parse("timezone = \"America/New_York\"").unwrap();
+
+        assert_eq!(
+            config.timezone.get().0,
+            Some(Zone::Named(chrono_tz::America::New_York))
+        );
+        assert!(parse("timezone = \"Nowhere/Special\"").is_err());
+    }
+
+    #[test]
+    fn parse_should_fail_given_unknown_key() {
+        assert!(parse("dta = \"/tmp\"").is_err());
+    }
+}
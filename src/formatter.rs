@@ -1,8 +1,37 @@
-use std::collections::{hash_map::Entry, HashMap};
-
-use crossterm::style::{StyledContent, Stylize};
-
-use crate::Task;
+use std::collections::{hash_map::Entry, BTreeSet, HashMap};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    prelude::*,
+};
+use crossterm::style::{ContentStyle, StyledContent, Stylize};
+use itertools::Itertools;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    formatter::template::Template,
+    history::ChangeKind,
+    task::{Priority, Tag},
+    timezone::Zone,
+    tracking::{format_duration, Minutes},
+    Task, TaskId,
+};
+
+pub mod template;
+
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Returns true if dates can be formatted with `format`, see [`chrono::format::strftime`].
+/// Formatting a date with an invalid format panics.
+/// ```
+/// use today::formatter::is_valid_date_format;
+///
+/// assert!(is_valid_date_format("%Y-%m-%d %H:%M"));
+/// assert!(!is_valid_date_format("%Q"));
+/// ```
+pub fn is_valid_date_format(format: &str) -> bool {
+    StrftimeItems::new(format).all(|x| !matches!(x, Item::Error))
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum Visibility {
@@ -93,6 +122,46 @@ impl std::fmt::Display for Cell {
 }
 
 pub type Format = String;
+
+/// The priority of a task, tasks without a priority are left empty.
+fn priority(task: &Task) -> String {
+    match task.priority() {
+        Priority::None => String::new(),
+        x => x.to_string(),
+    }
+}
+
+/// The due date of a task as the wall clock time in its own time zone, or else in `zone`.
+fn time(task: &Task, zone: Zone, format: &str) -> String {
+    let zone = task.zone_or(zone);
+    task.due().map_or(String::from("Now"), |x| {
+        zone.from_utc(x).format(format).to_string()
+    })
+}
+
+/// How many of the checklist items are done, e.g. `2/5`. Tasks without a checklist are
+/// left empty.
+fn progress(task: &Task) -> String {
+    task.progress()
+        .map(|(done, total)| format!("{done}/{total}"))
+        .unwrap_or_default()
+}
+
+fn tags(task: &Task) -> String {
+    task.tags().iter().map(|x| x.as_str()).join(" ")
+}
+
+/// Cross out the formatted output of tasks that have been completed.
+fn strike_completed(task: &Task, line: Format) -> Format {
+    if task.is_completed() {
+        StyledContent::new(ContentStyle::new(), line)
+            .crossed_out()
+            .to_string()
+    } else {
+        line
+    }
+}
+
 pub trait TaskFormatter {
     fn format(&self, task: &Task) -> Format;
 }
@@ -100,15 +169,28 @@ pub trait TaskFormatter {
 #[derive(Debug, Clone)]
 pub struct TodayFormatter {
     columns: HashMap<Field, Column>,
+    layout: Layout,
+    zone: Zone,
 }
 
 impl TodayFormatter {
     pub fn new() -> Self {
         Self {
             columns: HashMap::new(),
+            layout: Layout::default(),
+            zone: Zone::Local,
         }
     }
 
+    pub fn with_layout(self, layout: Layout) -> Self {
+        Self { layout, ..self }
+    }
+
+    /// Show due dates in `zone` rather than the system time zone.
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self { zone, ..self }
+    }
+
     pub fn column(&mut self, field: Field) -> Entry<'_, Field, Column> {
         self.columns.entry(field)
     }
@@ -126,39 +208,65 @@ impl Default for TodayFormatter {
 
 impl TaskFormatter for TodayFormatter {
     fn format(&self, task: &Task) -> Format {
-        let id = task.id().as_ref().to_simple().to_string();
-        let id = self
+        let line = self
+            .layout
             .columns
-            .get(&Field::Id)
-            .cloned()
-            .unwrap_or_default()
-            .cell()
-            .with_content(id);
-        let name = Cell::new(task.name());
-        let time = task.due().map_or(String::from("Now"), |x| {
-            x.format("%Y-%m-%d %H:%M").to_string()
-        });
-        let time = Cell::new(time);
-        format!(
-            "{}{}: {}",
-            id,
-            StyledContent::new(Default::default(), time).red(),
-            name
-        )
+            .iter()
+            .map(|field| match field {
+                Field::Id => {
+                    let id = task.id().as_ref().to_simple().to_string();
+                    self.columns
+                        .get(&Field::Id)
+                        .cloned()
+                        .unwrap_or_default()
+                        .cell()
+                        .with_content(id)
+                        .to_string()
+                }
+                Field::Name => Cell::new(task.name()).to_string(),
+                Field::Priority => priority(task),
+                Field::Progress => progress(task),
+                Field::Tags => tags(task),
+                Field::Time => {
+                    let time = time(task, self.zone, &self.layout.date_format);
+                    format!(
+                        "{}:",
+                        StyledContent::new(Default::default(), Cell::new(time)).red()
+                    )
+                }
+            })
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        strike_completed(task, line)
     }
 }
 
 pub struct ListFormatter {
     columns: HashMap<Field, Column>,
+    layout: Layout,
+    zone: Zone,
 }
 
 impl ListFormatter {
     pub fn new() -> Self {
         Self {
             columns: HashMap::new(),
+            layout: Layout::default(),
+            zone: Zone::Local,
         }
     }
 
+    pub fn with_layout(self, layout: Layout) -> Self {
+        Self { layout, ..self }
+    }
+
+    /// Show due dates in `zone` rather than the system time zone.
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self { zone, ..self }
+    }
+
     pub fn column(&mut self, field: Field) -> Entry<'_, Field, Column> {
         self.columns.entry(field)
     }
@@ -176,40 +284,407 @@ impl Default for ListFormatter {
 
 impl TaskFormatter for ListFormatter {
     fn format(&self, task: &Task) -> Format {
-        let id = task.id().as_ref().to_simple().to_string();
-        let id = self
+        let line = self
+            .layout
             .columns
-            .get(&Field::Id)
-            .cloned()
-            .unwrap_or_default()
-            .cell()
-            .with_content(id);
-        let name = self
-            .columns
-            .get(&Field::Name)
-            .cloned()
-            .unwrap_or_default()
-            .cell()
-            .with_content(task.name());
-        let time = task.due().map_or(String::from("Now"), |x| {
-            x.format("%Y-%m-%d %H:%M").to_string()
-        });
-        let time = self
-            .columns
-            .get(&Field::Time)
-            .cloned()
-            .unwrap_or_default()
-            .cell()
-            .with_content(time);
-        format!("{}{}{}", id, time, name)
+            .iter()
+            .map(|field| {
+                let content = match field {
+                    Field::Id => task.id().as_ref().to_simple().to_string(),
+                    Field::Name => task.name().to_owned(),
+                    Field::Priority => priority(task),
+                    Field::Progress => progress(task),
+                    Field::Tags => tags(task),
+                    Field::Time => time(task, self.zone, &self.layout.date_format),
+                };
+
+                self.columns
+                    .get(field)
+                    .cloned()
+                    .unwrap_or_default()
+                    .cell()
+                    .with_content(content)
+                    .to_string()
+            })
+            .collect();
+
+        strike_completed(task, line)
+    }
+}
+
+/// A task as a JSON object on a single line. Next to the fields of the task itself it has
+/// the fields as the other formatters resolve them: the due date as the wall clock time in
+/// the time zone of the task, whether the task is overdue and the shortest prefix of its id.
+/// ```
+/// use today::{formatter::{JsonFormatter, TaskFormatter}, timezone::Zone, Task, TaskName};
+/// use chrono::prelude::*;
+///
+/// let task = Task::new(TaskName::new("Pay rent").unwrap())
+///     .with_date_time(Utc.ymd(2022, 6, 1).and_hms(8, 0, 0))
+///     .with_timezone(Some("Europe/Stockholm".parse::<Zone>().unwrap()));
+/// let formatter = JsonFormatter::new()
+///     .with_id_length(4)
+///     .with_now(Utc.ymd(2022, 6, 2).and_hms(8, 0, 0));
+///
+/// let json = serde_json::from_str::<serde_json::Value>(&formatter.format(&task)).unwrap();
+///
+/// assert_eq!(json["name"], "Pay rent");
+/// assert_eq!(json["local_due"], "2022-06-01T10:00:00");
+/// assert_eq!(json["overdue"], true);
+/// assert_eq!(json["short_id"].as_str().unwrap().len(), 4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonFormatter {
+    zone: Zone,
+    id_length: usize,
+    now: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct Resolved<'a> {
+    #[serde(flatten)]
+    task: &'a Task,
+    short_id: String,
+    local_due: Option<NaiveDateTime>,
+    overdue: bool,
+}
+
+impl JsonFormatter {
+    pub fn new() -> Self {
+        Self {
+            zone: Zone::Local,
+            id_length: 32,
+            now: Utc::now(),
+        }
+    }
+
+    /// The due date of tasks without a time zone of their own is given in `zone`.
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self { zone, ..self }
+    }
+
+    /// How many characters of the id it takes to tell the tasks apart.
+    pub fn with_id_length(self, id_length: usize) -> Self {
+        Self { id_length, ..self }
+    }
+
+    /// Tasks due before `now` are overdue.
+    pub fn with_now(self, now: DateTime<Utc>) -> Self {
+        Self { now, ..self }
+    }
+}
+
+impl Default for JsonFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TaskFormatter for JsonFormatter {
+    fn format(&self, task: &Task) -> Format {
+        let id = task.id().as_ref().to_simple().to_string();
+        let zone = task.zone_or(self.zone);
+        let resolved = Resolved {
+            task,
+            short_id: id.chars().take(self.id_length).collect(),
+            local_due: task.due().map(|x| zone.from_utc(x)),
+            overdue: !task.is_completed() && task.due().is_some_and(|x| *x < self.now),
+        };
+
+        // Every field of a task can be serialized
+        serde_json::to_string(&resolved).unwrap()
+    }
+}
+
+/// Every field of a single task on a line of its own, followed by its notes and history.
+/// ```
+/// use today::{formatter::{DetailFormatter, TaskFormatter}, timezone::Zone, Task, TaskName};
+///
+/// let task = Task::new(TaskName::new("Plan trip +travel").unwrap())
+///     .with_notes(Some("Book hotel".to_owned()));
+/// let formatter = DetailFormatter::new().with_timezone("UTC".parse::<Zone>().unwrap());
+///
+/// let output = formatter.format(&task);
+///
+/// assert!(output.starts_with("Name:      Plan trip +travel\n"));
+/// assert!(output.contains("Tags:      +travel\n"));
+/// assert!(output.contains("Notes:\n  Book hotel"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DetailFormatter {
+    date_format: String,
+    zone: Zone,
+    tasks: Vec<Task>,
+}
+
+impl DetailFormatter {
+    pub fn new() -> Self {
+        Self {
+            date_format: String::from(DEFAULT_DATE_FORMAT),
+            zone: Zone::Local,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// The other tasks, used to show what a task depends on and what depends on it.
+    pub fn with_tasks(self, tasks: &[Task]) -> Self {
+        Self {
+            tasks: tasks.to_vec(),
+            ..self
+        }
+    }
+
+    pub fn with_date_format<T: Into<String>>(self, date_format: T) -> Self {
+        Self {
+            date_format: date_format.into(),
+            ..self
+        }
+    }
+
+    /// Show dates in `zone` rather than the system time zone. Due dates of tasks with a
+    /// time zone of their own are shown in that.
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self { zone, ..self }
+    }
+
+    /// A date and time as it is shown by this formatter.
+    pub fn date(&self, date: &DateTime<Utc>) -> String {
+        self.date_in(date, self.zone)
+    }
+
+    /// A date and time as the wall clock time in `zone`.
+    fn date_in(&self, date: &DateTime<Utc>, zone: Zone) -> String {
+        zone.from_utc(date).format(&self.date_format).to_string()
+    }
+
+    fn due(&self, task: &Task, due: Option<&DateTime<Utc>>) -> String {
+        let zone = task.zone_or(self.zone);
+        due.map_or(String::from("Now"), |x| self.date_in(x, zone))
+    }
+
+    /// The first characters of an id followed by the name of the task, if it is known.
+    fn reference(&self, id: &TaskId) -> String {
+        let short = id.to_string()[..5].to_owned();
+        match self.tasks.iter().find(|x| x.id() == id) {
+            Some(task) => format!("{short} {}", task.name()),
+            None => format!("{short} (removed)"),
+        }
+    }
+
+    /// A line for every task `task` depends on, followed by the tasks those depend on
+    /// and so on, indented by how far down the chain they are.
+    fn chain(&self, task: &Task, depth: usize, visited: &mut Vec<TaskId>, lines: &mut Vec<String>) {
+        for id in task.depends_on() {
+            let dependency = self.tasks.iter().find(|x| x.id() == id);
+            let tick = match dependency {
+                Some(x) if x.is_completed() => "[x] ",
+                Some(_) => "[ ] ",
+                None => "",
+            };
+            lines.push(format!(
+                "{:indent$}{tick}{}",
+                "",
+                self.reference(id),
+                indent = depth * 2
+            ));
+
+            // The tasks can't wait for each other, but a file edited by hand can still
+            // have them do so
+            if let Some(dependency) = dependency {
+                if !visited.contains(id) {
+                    visited.push(id.clone());
+                    self.chain(dependency, depth + 1, visited, lines);
+                }
+            }
+        }
+    }
+
+    /// A description of what changed in `task`.
+    pub fn change(&self, task: &Task, change: &ChangeKind) -> String {
+        let tags = |x: &BTreeSet<Tag>| match x.is_empty() {
+            true => String::from("none"),
+            false => x.iter().join(" "),
+        };
+
+        match change {
+            ChangeKind::Created => String::from("created"),
+            ChangeKind::Renamed { from, to } => format!("renamed from '{from}' to '{to}'"),
+            ChangeKind::Due { from, to } => format!(
+                "due changed from {} to {}",
+                self.due(task, from.as_ref()),
+                self.due(task, to.as_ref())
+            ),
+            ChangeKind::Completed => String::from("completed"),
+            ChangeKind::Reopened => String::from("reopened"),
+            ChangeKind::Removed => String::from("removed"),
+            ChangeKind::Restored => String::from("restored"),
+            ChangeKind::Priority { from, to } => format!("priority changed from {from} to {to}"),
+            ChangeKind::Repeat { from, to } => {
+                let repeat = |x: &Option<_>| {
+                    x.as_ref()
+                        .map_or(String::from("never"), ToString::to_string)
+                };
+                format!("repeat changed from {} to {}", repeat(from), repeat(to))
+            }
+            ChangeKind::Tags { from, to } => {
+                format!("tags changed from {} to {}", tags(from), tags(to))
+            }
+            ChangeKind::Timezone { from, to } => format!(
+                "time zone changed from {} to {}",
+                from.unwrap_or_default(),
+                to.unwrap_or_default()
+            ),
+            ChangeKind::Notes => String::from("notes changed"),
+            ChangeKind::ItemAdded { name } => format!("added '{name}' to the checklist"),
+            ChangeKind::ItemRemoved { name } => format!("removed '{name}' from the checklist"),
+            ChangeKind::ItemTicked { name } => format!("ticked off '{name}'"),
+            ChangeKind::ItemUnticked { name } => format!("unticked '{name}'"),
+            ChangeKind::DependencyAdded { id } => format!("now waits for {}", self.reference(id)),
+            ChangeKind::DependencyRemoved { id } => {
+                format!("no longer waits for {}", self.reference(id))
+            }
+            ChangeKind::Estimate { from, to } => {
+                let estimate =
+                    |x: &Option<Minutes>| x.map_or(String::from("none"), |x| x.to_string());
+                format!(
+                    "estimate changed from {} to {}",
+                    estimate(from),
+                    estimate(to)
+                )
+            }
+        }
+    }
+}
+
+impl Default for DetailFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TaskFormatter for DetailFormatter {
+    fn format(&self, task: &Task) -> Format {
+        let mut lines = vec![
+            format!("Name:      {}", task.name()),
+            format!("Id:        {}", task.id()),
+            format!("Due:       {}", self.due(task, task.due())),
+            format!("Priority:  {}", task.priority()),
+        ];
+        if let Some(repeat) = task.repeat() {
+            lines.push(format!("Repeat:    {repeat}"));
+        }
+        if !task.tags().is_empty() {
+            lines.push(format!("Tags:      {}", tags(task)));
+        }
+        if let Some(zone) = task.timezone() {
+            lines.push(format!("Time zone: {zone}"));
+        }
+        if let Some(completed) = task.completed() {
+            lines.push(format!("Completed: {}", self.date(completed)));
+        }
+        if let Some(estimate) = task.estimate() {
+            lines.push(format!("Estimate:  {estimate}"));
+        }
+        if !task.time_entries().is_empty() {
+            let now = Utc::now();
+            let tracked = format_duration(task.time_spent(now));
+            let running = if task.is_running() { " (running)" } else { "" };
+            lines.push(format!("Tracked:   {tracked}{running}"));
+        }
+
+        if let Some((done, total)) = task.progress() {
+            lines.push(format!("Checklist: {done}/{total}"));
+            lines.extend(task.checklist().iter().enumerate().map(|(i, x)| {
+                let tick = if x.is_done() { 'x' } else { ' ' };
+                format!("  {}. [{tick}] {}", i + 1, x.name())
+            }));
+        }
+
+        if !task.depends_on().is_empty() {
+            if task.is_blocked(&self.tasks) {
+                lines.push(String::from("Blocked:   yes"));
+            }
+            lines.push(String::from("Depends on:"));
+            self.chain(task, 1, &mut vec![task.id().clone()], &mut lines);
+        }
+        let needed_by = self
+            .tasks
+            .iter()
+            .filter(|x| x.depends_on().contains(task.id()))
+            .map(|x| format!("  {}", self.reference(x.id())))
+            .collect::<Vec<_>>();
+        if !needed_by.is_empty() {
+            lines.push(String::from("Needed by:"));
+            lines.extend(needed_by);
+        }
+
+        if let Some(notes) = task.notes() {
+            lines.push(String::from("Notes:"));
+            lines.extend(
+                notes
+                    .lines()
+                    .map(|x| format!("  {x}").trim_end().to_owned()),
+            );
+        }
+
+        lines.push(String::from("History:"));
+        lines.extend(
+            task.history()
+                .iter()
+                .map(|x| format!("  {} {}", self.date(&x.time), self.change(task, &x.kind))),
+        );
+
+        lines.join("\n")
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Field {
     Id,
     Name,
     Time,
+    Priority,
+    Progress,
+    Tags,
+}
+
+/// The order of the fields a formatter outputs and how dates are displayed.
+/// Fields not present in `columns` are left out of the output. A `template` replaces the
+/// columns altogether.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    #[serde(deserialize_with = "date_format")]
+    pub date_format: String,
+    pub columns: Vec<Field>,
+    pub template: Option<Template>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            date_format: String::from(DEFAULT_DATE_FORMAT),
+            columns: vec![
+                Field::Id,
+                Field::Priority,
+                Field::Progress,
+                Field::Time,
+                Field::Tags,
+                Field::Name,
+            ],
+            template: None,
+        }
+    }
+}
+
+fn date_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let format = String::deserialize(deserializer)?;
+    if is_valid_date_format(&format) {
+        Ok(format)
+    } else {
+        Err(de::Error::custom(format!("invalid date format '{format}'")))
+    }
 }
 
 #[derive(Debug, Clone, Default)]
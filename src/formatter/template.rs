@@ -0,0 +1,469 @@
+use std::{convert::TryFrom, iter::Peekable, str::Chars, str::FromStr};
+
+use crossterm::style::{Attribute, Color, ContentStyle, StyledContent};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{
+    is_valid_date_format, priority, progress, strike_completed, tags, Format, TaskFormatter,
+    DEFAULT_DATE_FORMAT,
+};
+use crate::{timezone::Zone, Task};
+
+/// A line of text with placeholders for the fields of a task.
+///
+/// A placeholder is written `{field!style:spec|fallback}` where everything but the field is
+/// optional:
+/// - `field` is one of `id`, `name`, `due`, `priority`, `progress`, `tags` or `estimate`
+/// - `style` is a comma separated list of colors, like `red` or `dark_grey`, and the
+///   attributes `bold`, `dim`, `italic`, `underlined` and `crossed`
+/// - `spec` is the alignment, `<`, `>` or `^`, and the width of the field. The id is cut
+///   to the width. The due date can be followed by its format, like `%a %H:%M`
+/// - `fallback` is written instead of a field that is empty, like a task without a due date
+///
+/// Text inside `{? ... }` is only written if none of the fields in it are empty. Braces are
+/// written as `{{` and `}}`.
+/// ```
+/// use today::{formatter::template::Template, task::Priority, Task, TaskName};
+///
+/// let template = "{id:5} {?[{priority}] }{name}{due:>8%H:%M|ASAP}"
+///     .parse::<Template>()
+///     .unwrap();
+/// let task = Task::new(TaskName::new("Call Sam").unwrap()).with_priority(Priority::High);
+///
+/// let line = template.render(&task, Default::default(), "%Y-%m-%d");
+///
+/// assert_eq!(line, format!("{} [high] Call Sam    ASAP", &task.id().to_string()[..5]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder(Placeholder),
+    /// Parts that are only written if none of their fields are empty.
+    Group(Vec<Part>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Placeholder {
+    field: TemplateField,
+    style: ContentStyle,
+    align: Align,
+    width: Option<usize>,
+    date_format: Option<String>,
+    fallback: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TemplateField {
+    Id,
+    Name,
+    Due,
+    Priority,
+    Progress,
+    Tags,
+    Estimate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error(
+        "Unknown field '{0}', expected one of id, name, due, priority, progress, tags or estimate"
+    )]
+    UnknownField(String),
+    #[error(
+        "Unknown style '{0}', expected a color or one of bold, dim, italic, underlined or crossed"
+    )]
+    UnknownStyle(String),
+    #[error("Invalid format '{0}' for the field '{1}', only the due date has a format")]
+    InvalidSpec(String, String),
+    #[error("Invalid date format '{0}' for the due date")]
+    InvalidDateFormat(String),
+    #[error("Missing '}}' at the end of the template")]
+    Unclosed,
+    #[error("Unexpected '}}' at {0}, write '}}}}' for a brace")]
+    UnexpectedBrace(usize),
+}
+
+impl Template {
+    /// The template filled in with the fields of `task`. Due dates are written as the wall
+    /// clock time in the time zone of the task, or `zone` if it has none, in `date_format`
+    /// unless the placeholder has a format of its own.
+    pub fn render(&self, task: &Task, zone: Zone, date_format: &str) -> String {
+        render(&self.parts, task, zone, date_format).0
+    }
+}
+
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cursor = Cursor {
+            chars: s.chars().peekable(),
+            position: 0,
+        };
+        let parts = parse(&mut cursor, false)?;
+
+        Ok(Self {
+            source: s.to_owned(),
+            parts,
+        })
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// The characters of a template and how many of them have been read.
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    position: usize,
+}
+
+impl Cursor<'_> {
+    fn next(&mut self) -> Option<char> {
+        self.position += 1;
+        self.chars.next()
+    }
+
+    /// Read the next character if it is `ch`.
+    fn next_if(&mut self, ch: char) -> bool {
+        let found = self.chars.peek() == Some(&ch);
+        if found {
+            self.next();
+        }
+        found
+    }
+}
+
+/// The parts up to the end of the text, or up to the closing brace if in a group.
+fn parse(cursor: &mut Cursor<'_>, in_group: bool) -> Result<Vec<Part>, TemplateError> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+
+    loop {
+        match cursor.next() {
+            None if in_group => return Err(TemplateError::Unclosed),
+            None => break,
+            Some('{') if cursor.next_if('{') => text.push('{'),
+            Some('{') => {
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                if cursor.next_if('?') {
+                    parts.push(Part::Group(parse(cursor, true)?));
+                } else {
+                    let mut body = String::new();
+                    loop {
+                        match cursor.next() {
+                            Some('}') => break,
+                            Some(x) => body.push(x),
+                            None => return Err(TemplateError::Unclosed),
+                        }
+                    }
+                    parts.push(Part::Placeholder(body.parse()?));
+                }
+            }
+            Some('}') if cursor.next_if('}') => text.push('}'),
+            Some('}') if in_group => break,
+            Some('}') => return Err(TemplateError::UnexpectedBrace(cursor.position - 1)),
+            Some(x) => text.push(x),
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+impl FromStr for Placeholder {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (head, fallback) = match s.split_once('|') {
+            Some((head, fallback)) => (head, Some(fallback.to_owned())),
+            None => (s, None),
+        };
+        let (head, spec) = head.split_once(':').unwrap_or((head, ""));
+        let (name, styles) = head.split_once('!').unwrap_or((head, ""));
+
+        let field = name.trim().parse::<TemplateField>()?;
+        let style = styles
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .try_fold(ContentStyle::new(), style)?;
+
+        let (align, spec) = match spec.chars().next() {
+            Some('<') => (Align::Left, &spec[1..]),
+            Some('>') => (Align::Right, &spec[1..]),
+            Some('^') => (Align::Center, &spec[1..]),
+            _ => (Align::Left, spec),
+        };
+        let digits = spec
+            .find(|x: char| !x.is_ascii_digit())
+            .unwrap_or(spec.len());
+        let width = spec[..digits].parse::<usize>().ok();
+        let date_format = match &spec[digits..] {
+            "" => None,
+            x if field == TemplateField::Due && is_valid_date_format(x) => Some(x.to_owned()),
+            x if field == TemplateField::Due => {
+                return Err(TemplateError::InvalidDateFormat(x.to_owned()))
+            }
+            x => {
+                return Err(TemplateError::InvalidSpec(
+                    x.to_owned(),
+                    name.trim().to_owned(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            field,
+            style,
+            align,
+            width,
+            date_format,
+            fallback,
+        })
+    }
+}
+
+impl FromStr for TemplateField {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(Self::Id),
+            "name" => Ok(Self::Name),
+            "due" => Ok(Self::Due),
+            "priority" => Ok(Self::Priority),
+            "progress" => Ok(Self::Progress),
+            "tags" => Ok(Self::Tags),
+            "estimate" => Ok(Self::Estimate),
+            _ => Err(TemplateError::UnknownField(s.to_owned())),
+        }
+    }
+}
+
+/// Add a color or an attribute to `style`.
+fn style(mut style: ContentStyle, name: &str) -> Result<ContentStyle, TemplateError> {
+    let attribute = match name {
+        "bold" => Some(Attribute::Bold),
+        "dim" => Some(Attribute::Dim),
+        "italic" => Some(Attribute::Italic),
+        "underlined" => Some(Attribute::Underlined),
+        "crossed" => Some(Attribute::CrossedOut),
+        _ => None,
+    };
+
+    match attribute {
+        Some(attribute) => style.attributes.set(attribute),
+        None => {
+            let color =
+                Color::try_from(name).map_err(|_| TemplateError::UnknownStyle(name.to_owned()))?;
+            style.foreground_color = Some(color);
+        }
+    }
+    Ok(style)
+}
+
+/// The text of `parts` and whether all of the fields in them had a value.
+fn render(parts: &[Part], task: &Task, zone: Zone, date_format: &str) -> (String, bool) {
+    let mut output = String::new();
+    let mut complete = true;
+
+    for part in parts {
+        match part {
+            Part::Text(text) => output.push_str(text),
+            Part::Placeholder(placeholder) => {
+                let value = placeholder.value(task, zone, date_format);
+                complete &= !value.is_empty();
+                output.push_str(&placeholder.write(value));
+            }
+            Part::Group(parts) => {
+                let (text, group_complete) = render(parts, task, zone, date_format);
+                if group_complete {
+                    output.push_str(&text);
+                }
+            }
+        }
+    }
+
+    (output, complete)
+}
+
+impl Placeholder {
+    /// The field of `task`, empty if the task doesn't have it.
+    fn value(&self, task: &Task, zone: Zone, date_format: &str) -> String {
+        match self.field {
+            TemplateField::Id => {
+                let id = task.id().as_ref().to_simple().to_string();
+                match self.width {
+                    Some(width) => id.chars().take(width).collect(),
+                    None => id,
+                }
+            }
+            TemplateField::Name => task.name().to_owned(),
+            TemplateField::Due => {
+                let zone = task.zone_or(zone);
+                let format = self.date_format.as_deref().unwrap_or(date_format);
+                task.due()
+                    .map(|x| zone.from_utc(x).format(format).to_string())
+                    .unwrap_or_default()
+            }
+            TemplateField::Priority => priority(task),
+            TemplateField::Progress => progress(task),
+            TemplateField::Tags => tags(task),
+            TemplateField::Estimate => task.estimate().map(|x| x.to_string()).unwrap_or_default(),
+        }
+    }
+
+    /// `value`, or the fallback if it is empty, aligned and styled.
+    fn write(&self, value: String) -> String {
+        let value = match &self.fallback {
+            Some(fallback) if value.is_empty() => fallback.clone(),
+            _ => value,
+        };
+        let width = self.width.unwrap_or_default();
+        let value = match self.align {
+            Align::Left => format!("{value:<width$}"),
+            Align::Right => format!("{value:>width$}"),
+            Align::Center => format!("{value:^width$}"),
+        };
+
+        if self.style == ContentStyle::new() || value.is_empty() {
+            value
+        } else {
+            StyledContent::new(self.style, value).to_string()
+        }
+    }
+}
+
+/// Formats tasks with a [`Template`].
+#[derive(Debug, Clone)]
+pub struct TemplateFormatter {
+    template: Template,
+    date_format: String,
+    zone: Zone,
+}
+
+impl TemplateFormatter {
+    pub fn new(template: Template) -> Self {
+        Self {
+            template,
+            date_format: String::from(DEFAULT_DATE_FORMAT),
+            zone: Zone::Local,
+        }
+    }
+
+    /// The format of due dates whose placeholder has no format of its own.
+    pub fn with_date_format<T: Into<String>>(self, date_format: T) -> Self {
+        Self {
+            date_format: date_format.into(),
+            ..self
+        }
+    }
+
+    /// Show due dates in `zone` rather than the system time zone.
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self { zone, ..self }
+    }
+}
+
+impl TaskFormatter for TemplateFormatter {
+    fn format(&self, task: &Task) -> Format {
+        let line = self.template.render(task, self.zone, &self.date_format);
+        strike_completed(task, line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{task::Priority, tracking::Minutes, TaskName};
+    use chrono::prelude::*;
+    use test_case::test_case;
+
+    fn task() -> Task {
+        Task::new(TaskName::new("Review +work").unwrap())
+            .with_date_time(Utc.ymd(2022, 6, 15).and_hms(9, 30, 0))
+            .with_estimate(Some(Minutes::new(45)))
+    }
+
+    fn render(template: &str, task: &Task) -> String {
+        let zone = "UTC".parse::<Zone>().unwrap();
+        template
+            .parse::<Template>()
+            .unwrap()
+            .render(task, zone, DEFAULT_DATE_FORMAT)
+    }
+
+    #[test_case("{name}" => "Review +work")]
+    #[test_case("{due}" => "2022-06-15 09:30")]
+    #[test_case("{due:%a %H:%M}" => "Wed 09:30")]
+    #[test_case("[{name:>14}]" => "[  Review +work]")]
+    #[test_case("[{estimate:^5}]" => "[ 45m ]")]
+    #[test_case("{priority|-} {tags}" => "- +work")]
+    #[test_case("{?({priority}) }{name}" => "Review +work")]
+    #[test_case("{?({estimate}) }{name}" => "(45m) Review +work")]
+    #[test_case("{{{progress}}}" => "{}")]
+    #[test_case("{?{due} due}}}" => "2022-06-15 09:30 due}")]
+    #[test_case("{?{{{estimate}}} }{due}" => "{45m} 2022-06-15 09:30")]
+    fn render_should_fill_in_fields(template: &str) -> String {
+        render(template, &task())
+    }
+
+    #[test]
+    fn render_should_cut_id_to_width_and_use_fallback_for_missing_due() {
+        let task = task().with_due(None).with_priority(Priority::Low);
+
+        let output = render("{id:6} {priority} {due|ASAP}", &task);
+
+        assert_eq!(output, format!("{} low ASAP", &task.id().to_string()[..6]));
+    }
+
+    #[test]
+    fn render_should_style_fields() {
+        let output = render("{name!red,bold}", &task());
+
+        assert!(output.contains("Review +work"));
+        assert_ne!(output, "Review +work");
+    }
+
+    #[test_case("{title}" => TemplateError::UnknownField("title".to_owned()))]
+    #[test_case("{name!sparkly}" => TemplateError::UnknownStyle("sparkly".to_owned()))]
+    #[test_case("{name:10%H}" => TemplateError::InvalidSpec("%H".to_owned(), "name".to_owned()))]
+    #[test_case("{due:>16%Y-%Q}" => TemplateError::InvalidDateFormat("%Y-%Q".to_owned()))]
+    #[test_case("{name" => TemplateError::Unclosed)]
+    #[test_case("{?[{priority}] " => TemplateError::Unclosed)]
+    #[test_case("a}b" => TemplateError::UnexpectedBrace(1))]
+    fn parse_should_fail_given_invalid_template(template: &str) -> TemplateError {
+        template.parse::<Template>().unwrap_err()
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeSet;
+
+use chrono::prelude::*;
+use itertools::{EitherOrBoth, Itertools};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    recurrence::Recurrence,
+    task::{ChecklistItem, Priority, Tag},
+    timezone::Zone,
+    tracking::Minutes,
+    Task, TaskId,
+};
+
+/// Something that happened to a task at some point in time.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Change {
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Renamed {
+        from: String,
+        to: String,
+    },
+    Due {
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    },
+    Completed,
+    Reopened,
+    Priority {
+        from: Priority,
+        to: Priority,
+    },
+    Repeat {
+        from: Option<Recurrence>,
+        to: Option<Recurrence>,
+    },
+    Tags {
+        from: BTreeSet<Tag>,
+        to: BTreeSet<Tag>,
+    },
+    Timezone {
+        from: Option<Zone>,
+        to: Option<Zone>,
+    },
+    Notes,
+    ItemAdded {
+        name: String,
+    },
+    ItemRemoved {
+        name: String,
+    },
+    ItemTicked {
+        name: String,
+    },
+    ItemUnticked {
+        name: String,
+    },
+    DependencyAdded {
+        id: TaskId,
+    },
+    DependencyRemoved {
+        id: TaskId,
+    },
+    Estimate {
+        from: Option<Minutes>,
+        to: Option<Minutes>,
+    },
+    Removed,
+    Restored,
+}
+
+/// What changed between `old` and `new`, the history and the time entries of the tasks are
+/// not compared.
+/// ```
+/// use today::{history::{changes, ChangeKind}, Task, TaskName};
+/// use chrono::prelude::*;
+///
+/// let old = Task::new(TaskName::new("Report").unwrap());
+/// let new = old
+///     .clone()
+///     .with_name(TaskName::new("Quarterly report").unwrap())
+///     .with_completed(Some(Utc::now()));
+///
+/// assert_eq!(
+///     changes(&old, &new),
+///     vec![
+///         ChangeKind::Renamed {
+///             from: "Report".to_owned(),
+///             to: "Quarterly report".to_owned()
+///         },
+///         ChangeKind::Completed,
+///     ]
+/// );
+/// ```
+pub fn changes(old: &Task, new: &Task) -> Vec<ChangeKind> {
+    let mut changes = Vec::new();
+
+    if old.name() != new.name() {
+        changes.push(ChangeKind::Renamed {
+            from: old.name().to_owned(),
+            to: new.name().to_owned(),
+        });
+    }
+    if old.due() != new.due() {
+        changes.push(ChangeKind::Due {
+            from: old.due().cloned(),
+            to: new.due().cloned(),
+        });
+    }
+    match (old.is_completed(), new.is_completed()) {
+        (false, true) => changes.push(ChangeKind::Completed),
+        (true, false) => changes.push(ChangeKind::Reopened),
+        _ => {}
+    }
+    if old.priority() != new.priority() {
+        changes.push(ChangeKind::Priority {
+            from: old.priority(),
+            to: new.priority(),
+        });
+    }
+    if old.repeat() != new.repeat() {
+        changes.push(ChangeKind::Repeat {
+            from: old.repeat().cloned(),
+            to: new.repeat().cloned(),
+        });
+    }
+    if old.explicit_tags() != new.explicit_tags() {
+        changes.push(ChangeKind::Tags {
+            from: old.explicit_tags().clone(),
+            to: new.explicit_tags().clone(),
+        });
+    }
+    if old.timezone() != new.timezone() {
+        changes.push(ChangeKind::Timezone {
+            from: old.timezone(),
+            to: new.timezone(),
+        });
+    }
+    // Notes can be long, so only that they changed is kept
+    if old.notes() != new.notes() {
+        changes.push(ChangeKind::Notes);
+    }
+    if old.estimate() != new.estimate() {
+        changes.push(ChangeKind::Estimate {
+            from: old.estimate(),
+            to: new.estimate(),
+        });
+    }
+    // Time tracking is kept in the time entries of the task, so it isn't repeated here
+    changes.extend(checklist_changes(old.checklist(), new.checklist()));
+    changes.extend(
+        new.depends_on()
+            .difference(old.depends_on())
+            .map(|id| ChangeKind::DependencyAdded { id: id.clone() }),
+    );
+    changes.extend(
+        old.depends_on()
+            .difference(new.depends_on())
+            .map(|id| ChangeKind::DependencyRemoved { id: id.clone() }),
+    );
+
+    changes
+}
+
+/// Items are matched by their position, as they are only ever added last.
+fn checklist_changes(old: &[ChecklistItem], new: &[ChecklistItem]) -> Vec<ChangeKind> {
+    let added = |x: &ChecklistItem| ChangeKind::ItemAdded {
+        name: x.name().to_owned(),
+    };
+    let removed = |x: &ChecklistItem| ChangeKind::ItemRemoved {
+        name: x.name().to_owned(),
+    };
+
+    old.iter()
+        .zip_longest(new)
+        .flat_map(|x| match x {
+            EitherOrBoth::Both(old, new) if old.name() != new.name() => {
+                vec![removed(old), added(new)]
+            }
+            EitherOrBoth::Both(old, new) => match (old.is_done(), new.is_done()) {
+                (false, true) => vec![ChangeKind::ItemTicked {
+                    name: new.name().to_owned(),
+                }],
+                (true, false) => vec![ChangeKind::ItemUnticked {
+                    name: new.name().to_owned(),
+                }],
+                _ => vec![],
+            },
+            EitherOrBoth::Left(old) => vec![removed(old)],
+            EitherOrBoth::Right(new) => vec![added(new)],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TaskList, TaskName};
+
+    fn kinds(task: &Task) -> Vec<ChangeKind> {
+        task.history().iter().map(|x| x.kind.clone()).collect()
+    }
+
+    #[test]
+    fn edit_should_append_changes_to_history() {
+        let mut tasks = TaskList::new();
+        let task = Task::new(TaskName::new("Report").unwrap());
+        tasks.add(task.clone());
+
+        let due = Utc.ymd(2022, 6, 16).and_hms(9, 0, 0);
+        tasks.edit(task.clone().with_due(Some(due))).unwrap();
+        let current = tasks.iter().next().unwrap().clone();
+        tasks.edit(current.with_priority(Priority::High)).unwrap();
+
+        assert_eq!(
+            kinds(&tasks[0]),
+            vec![
+                ChangeKind::Created,
+                ChangeKind::Due {
+                    from: None,
+                    to: Some(due)
+                },
+                ChangeKind::Priority {
+                    from: Priority::None,
+                    to: Priority::High
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn edit_should_keep_history_given_replacement_without_it() {
+        let mut tasks = TaskList::new();
+        let task = Task::new(TaskName::new("Report").unwrap());
+        tasks.add(task.clone());
+
+        // The replacement was made from the task before it was added
+        tasks.edit(task.with_completed(Some(Utc::now()))).unwrap();
+
+        assert_eq!(
+            kinds(&tasks[0]),
+            vec![ChangeKind::Created, ChangeKind::Completed]
+        );
+    }
+
+    #[test]
+    fn changes_should_find_added_and_ticked_items() {
+        let item = |x| ChecklistItem::new(TaskName::new(x).unwrap());
+        let old = Task::new(TaskName::new("Release v2").unwrap()).with_item(item("Tag"));
+        let new = old.clone().with_item(item("Build")).with_ticked(0).unwrap();
+
+        assert_eq!(
+            changes(&old, &new),
+            vec![
+                ChangeKind::ItemTicked {
+                    name: "Tag".to_owned()
+                },
+                ChangeKind::ItemAdded {
+                    name: "Build".to_owned()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn history_should_survive_serialization() {
+        let old = Task::new(TaskName::new("Report").unwrap());
+        let task = old
+            .clone()
+            .with_name(TaskName::new("Renamed").unwrap())
+            .with_history_of(&old, Utc::now());
+
+        let json = serde_json::to_string(&task).unwrap();
+
+        assert_eq!(serde_json::from_str::<Task>(&json).unwrap(), task);
+        assert!(json.contains(r#""change":"renamed""#));
+    }
+}
@@ -0,0 +1,421 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+};
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{repository::Repository, Task, TaskList};
+
+/// A single change to the tasks.
+// Only a handful of operations are made at a time, so boxing the tasks isn't worth it
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Add(Task),
+    Edit { old: Task, new: Task },
+    Remove(Task),
+}
+
+impl Operation {
+    /// The operation that takes the tasks back to how they were before this one.
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Add(task) => Self::Remove(task),
+            Self::Edit { old, new } => Self::Edit { old: new, new: old },
+            Self::Remove(task) => Self::Add(task),
+        }
+    }
+
+    /// The task as it is after the operation, or the removed task.
+    pub fn task(&self) -> &Task {
+        match self {
+            Self::Add(task) | Self::Remove(task) | Self::Edit { new: task, .. } => task,
+        }
+    }
+
+    /// Apply the operation to `tasks`, which have to look like they did right before it.
+    fn apply(&self, tasks: &mut TaskList) -> Result<(), JournalError> {
+        // The history keeps growing with every change, so it isn't compared
+        let without_history = |x: Option<&Task>| x.map(|x| x.clone().with_history(Vec::new()));
+        let current = tasks.iter().find(|x| x.id() == self.task().id());
+        let expected = match self {
+            Self::Add(_) => None,
+            Self::Edit { old, .. } => Some(old),
+            Self::Remove(task) => Some(task),
+        };
+        if without_history(current) != without_history(expected) {
+            return Err(JournalError::Changed(self.task().name().to_owned()));
+        }
+
+        match self {
+            Self::Add(task) => tasks.add(task.clone()),
+            Self::Edit { new, .. } => {
+                tasks.edit(new.clone()).map_err(std::io::Error::other)?;
+            }
+            Self::Remove(task) => tasks.remove(task.id()),
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self.task().id().to_string();
+        let id = &id[..5];
+        match self {
+            Self::Add(task) => write!(f, "add {id} {}", task.name()),
+            Self::Edit { old, new } if !old.is_completed() && new.is_completed() => {
+                write!(f, "done {id} {}", new.name())
+            }
+            Self::Edit { old, new } if old.name() != new.name() => {
+                write!(f, "edit {id} {} -> {}", old.name(), new.name())
+            }
+            Self::Edit { new, .. } => write!(f, "edit {id} {}", new.name()),
+            Self::Remove(task) => write!(f, "remove {id} {}", task.name()),
+        }
+    }
+}
+
+/// Why a group of operations was made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Do,
+    Undo,
+    Redo,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Do => write!(f, "do"),
+            Self::Undo => write!(f, "undo"),
+            Self::Redo => write!(f, "redo"),
+        }
+    }
+}
+
+/// The operations made by one command, they are undone and redone together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    pub action: Action,
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Error)]
+pub enum JournalError {
+    #[error("There is nothing to undo")]
+    NothingToUndo,
+    #[error("There is nothing to redo")]
+    NothingToRedo,
+    #[error("The task '{0}' has changed since, so the operation can't be replayed")]
+    Changed(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// An append-only log of every change made to the tasks, stored as one JSON [`Entry`] per
+/// line.
+///
+/// Undoing appends the inverse of the last change that isn't undone yet, so the journal
+/// itself is never rewritten. Any new change makes the undone changes impossible to redo.
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Record `operations` as one entry. Nothing is recorded if there are none.
+    pub fn append(&self, action: Action, operations: Vec<Operation>) -> std::io::Result<()> {
+        if operations.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let entry = Entry {
+            time: Utc::now(),
+            action,
+            operations,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        // A single write so that concurrent appends don't interleave
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+
+    /// All entries, oldest first.
+    pub fn entries(&self) -> std::io::Result<Vec<Entry>> {
+        let content = match fs::read_to_string(&self.path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+            x => x,
+        }?;
+
+        content
+            .lines()
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| Ok(serde_json::from_str(x)?))
+            .collect()
+    }
+
+    /// Revert the last change that isn't undone yet. Returns the operations made to revert it.
+    pub fn undo<R>(&self, repo: &R) -> Result<Vec<Operation>, JournalError>
+    where
+        R: Repository<Err = std::io::Error> + ?Sized,
+    {
+        let (mut done, _) = stacks(self.entries()?);
+        let operations = done.pop().ok_or(JournalError::NothingToUndo)?;
+        let inverse = operations
+            .into_iter()
+            .rev()
+            .map(Operation::inverse)
+            .collect::<Vec<_>>();
+
+        replay(repo, &inverse)?;
+        self.append(Action::Undo, inverse.clone())?;
+        Ok(inverse)
+    }
+
+    /// Make the last undone change again. Returns the operations made.
+    pub fn redo<R>(&self, repo: &R) -> Result<Vec<Operation>, JournalError>
+    where
+        R: Repository<Err = std::io::Error> + ?Sized,
+    {
+        let (_, mut undone) = stacks(self.entries()?);
+        let operations = undone.pop().ok_or(JournalError::NothingToRedo)?;
+
+        replay(repo, &operations)?;
+        self.append(Action::Redo, operations.clone())?;
+        Ok(operations)
+    }
+}
+
+/// The changes that can be undone and the ones that can be redone, the last ones on top.
+fn stacks(entries: Vec<Entry>) -> (Vec<Vec<Operation>>, Vec<Vec<Operation>>) {
+    let mut done = Vec::new();
+    let mut undone = Vec::new();
+
+    for entry in entries {
+        match entry.action {
+            Action::Do => {
+                done.push(entry.operations);
+                undone.clear();
+            }
+            Action::Undo => undone.extend(done.pop()),
+            Action::Redo => done.extend(undone.pop()),
+        }
+    }
+
+    (done, undone)
+}
+
+/// Apply `operations` to the repository. They are all checked against the current tasks
+/// first, so that either all of them or none are applied.
+fn replay<R>(repo: &R, operations: &[Operation]) -> Result<(), JournalError>
+where
+    R: Repository<Err = std::io::Error> + ?Sized,
+{
+    let mut tasks = repo.all()?;
+    for operation in operations {
+        operation.apply(&mut tasks)?;
+    }
+
+    Ok(repo.write(operations)?)
+}
+
+/// The operations that turn `before` into `after`.
+pub fn diff(before: &[Task], after: &[Task]) -> Vec<Operation> {
+    let mut before = before
+        .iter()
+        .map(|x| (x.id(), x))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut operations = Vec::new();
+    for task in after {
+        match before.remove(task.id()) {
+            None => operations.push(Operation::Add(task.clone())),
+            Some(old) if old != task => operations.push(Operation::Edit {
+                old: old.clone(),
+                new: task.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    operations.extend(before.into_values().cloned().map(Operation::Remove));
+
+    operations
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::{json::JsonRepository, TaskName};
+
+    fn task(name: &str) -> Task {
+        Task::new(TaskName::new(name).unwrap())
+    }
+
+    fn names(repo: &JsonRepository) -> Vec<String> {
+        repo.all()
+            .unwrap()
+            .iter()
+            .map(|x| x.name().to_owned())
+            .sorted()
+            .collect()
+    }
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        repo: JsonRepository,
+        journal: Journal,
+    }
+
+    fn fixture() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        Fixture {
+            repo: JsonRepository::new(dir.path().join("tasks.json")),
+            journal: Journal::new(dir.path().join("journal.jsonl")),
+            _dir: dir,
+        }
+    }
+
+    impl Fixture {
+        fn run(&self, operation: Operation) {
+            replay(&self.repo, std::slice::from_ref(&operation)).unwrap();
+            self.journal.append(Action::Do, vec![operation]).unwrap();
+        }
+    }
+
+    #[test]
+    fn undo_should_revert_the_last_change() {
+        let f = fixture();
+        let first = task("First");
+        let renamed = first.clone().with_name(TaskName::new("Renamed").unwrap());
+        f.run(Operation::Add(first.clone()));
+        f.run(Operation::Edit {
+            old: first,
+            new: renamed.clone(),
+        });
+        f.run(Operation::Remove(renamed));
+
+        f.journal.undo(&f.repo).unwrap();
+        assert_eq!(names(&f.repo), vec!["Renamed"]);
+        f.journal.undo(&f.repo).unwrap();
+        assert_eq!(names(&f.repo), vec!["First"]);
+        f.journal.undo(&f.repo).unwrap();
+        assert!(names(&f.repo).is_empty());
+        assert!(matches!(
+            f.journal.undo(&f.repo),
+            Err(JournalError::NothingToUndo)
+        ));
+    }
+
+    #[test]
+    fn redo_should_replay_undone_changes_in_order() {
+        let f = fixture();
+        f.run(Operation::Add(task("First")));
+        f.run(Operation::Add(task("Second")));
+        f.journal.undo(&f.repo).unwrap();
+        f.journal.undo(&f.repo).unwrap();
+
+        f.journal.redo(&f.repo).unwrap();
+        assert_eq!(names(&f.repo), vec!["First"]);
+        f.journal.redo(&f.repo).unwrap();
+        assert_eq!(names(&f.repo), vec!["First", "Second"]);
+        assert!(matches!(
+            f.journal.redo(&f.repo),
+            Err(JournalError::NothingToRedo)
+        ));
+    }
+
+    #[test]
+    fn new_change_should_discard_undone_changes() {
+        let f = fixture();
+        f.run(Operation::Add(task("First")));
+        f.journal.undo(&f.repo).unwrap();
+        f.run(Operation::Add(task("Second")));
+
+        assert!(matches!(
+            f.journal.redo(&f.repo),
+            Err(JournalError::NothingToRedo)
+        ));
+        assert_eq!(f.journal.entries().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn undo_should_fail_given_task_changed_outside_the_journal() {
+        let f = fixture();
+        let first = task("First");
+        f.run(Operation::Add(first.clone()));
+        f.repo
+            .update(first.with_name(TaskName::new("Changed").unwrap()))
+            .unwrap();
+
+        assert!(matches!(
+            f.journal.undo(&f.repo),
+            Err(JournalError::Changed(_))
+        ));
+        assert_eq!(names(&f.repo), vec!["Changed"]);
+    }
+
+    #[test]
+    fn undo_should_save_all_operations_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = JsonRepository::new(dir.path().join("tasks.json")).with_backups(5);
+        let journal = Journal::new(dir.path().join("journal.jsonl"));
+        let operations = vec![
+            Operation::Add(task("First")),
+            Operation::Add(task("Second")),
+        ];
+        replay(&repo, &operations).unwrap();
+        journal.append(Action::Do, operations).unwrap();
+
+        journal.undo(&repo).unwrap();
+
+        assert!(names(&repo).is_empty());
+        assert!(dir.path().join("tasks.json.1").exists());
+        assert!(!dir.path().join("tasks.json.2").exists());
+    }
+
+    #[test]
+    fn diff_should_find_added_edited_and_removed_tasks() {
+        let kept = task("Kept");
+        let edited = task("Edited");
+        let removed = task("Removed");
+        let added = task("Added");
+        let before = vec![kept.clone(), edited.clone(), removed.clone()];
+        let new = edited.clone().with_name(TaskName::new("New name").unwrap());
+        let after = vec![kept, new.clone(), added.clone()];
+
+        let operations = diff(&before, &after);
+
+        assert_eq!(
+            operations,
+            vec![
+                Operation::Edit { old: edited, new },
+                Operation::Add(added),
+                Operation::Remove(removed),
+            ]
+        );
+    }
+}
@@ -1,16 +1,153 @@
-use std::{fs, io::ErrorKind, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
 
+use chrono::prelude::*;
+use fs2::FileExt;
 use serde_json;
 
-use crate::{Task, TaskList};
+use crate::{journal::Operation, repository, Task, TaskList};
 
+/// Tasks stored as a JSON array in a single file.
+///
+/// Other instances may change the file between [`all`] and [`save`]. Reading and writing
+/// take an advisory lock on a file next to it, and a save merges with whatever changed on
+/// disk since the tasks were read, see [`repository::merge`]. Changes that can't be merged
+/// fail the save instead of overwriting the other changes.
+///
+/// Removed tasks are kept in a second file next to it, `tasks.removed.json` for
+/// `tasks.json`.
+///
+/// [`all`]: repository::Repository::all
+/// [`save`]: repository::Repository::save
 pub struct JsonRepository {
     path: PathBuf,
+    backups: usize,
+    read: RefCell<Option<Vec<Task>>>,
 }
 
 impl JsonRepository {
     pub fn new<P: Into<PathBuf>>(path: P) -> Self {
-        Self { path: path.into() }
+        Self {
+            path: path.into(),
+            backups: 0,
+            read: RefCell::new(None),
+        }
+    }
+
+    /// Keep the last `backups` versions of the file next to it as `tasks.json.1`,
+    /// `tasks.json.2` and so on, where `1` is the most recent.
+    pub fn with_backups(self, backups: usize) -> Self {
+        Self { backups, ..self }
+    }
+
+    fn backup_path(&self, version: usize) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_owned();
+        name.push(format!(".{version}"));
+        self.path.with_file_name(name)
+    }
+
+    fn removed_path(&self) -> PathBuf {
+        self.path.with_extension("removed.json")
+    }
+
+    /// Take a lock that is released when the returned file is dropped.
+    fn lock(&self, exclusive: bool) -> std::io::Result<File> {
+        let mut name = std::ffi::OsString::from(".");
+        name.push(self.path.file_name().unwrap_or_default());
+        name.push(".lock");
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(self.path.with_file_name(name))?;
+        if exclusive {
+            file.lock_exclusive()?;
+        } else {
+            file.lock_shared()?;
+        }
+
+        Ok(file)
+    }
+
+    fn read(&self) -> std::io::Result<Vec<Task>> {
+        read(&self.path)
+    }
+
+    fn create_directory(&self) -> std::io::Result<()> {
+        let directory = self
+            .path
+            .parent()
+            .expect("Expected a directory for the file");
+
+        match fs::metadata(directory) {
+            Err(err) if err.kind() == ErrorKind::NotFound => fs::create_dir_all(directory),
+            Err(err) => Err(err),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Write `tasks` in place of `current`, which is what the file holds. The lock has to be
+    /// held while calling this.
+    fn replace(&self, current: &[Task], tasks: &TaskList) -> std::io::Result<()> {
+        // The archive goes first, so a failure leaves the tasks as they were. Saving again
+        // gives the same archive.
+        self.archive(current, tasks)?;
+
+        let json = serde_json::to_string(&tasks.as_slice())?;
+        write_atomic(
+            &self.path,
+            |file| file.write_all(json.as_bytes()),
+            || self.rotate_backups(),
+        )
+    }
+
+    /// Keep the tasks in `old` that are not in `new` as removed, and forget the removed tasks
+    /// that are in `new` again.
+    fn archive(&self, old: &[Task], new: &TaskList) -> std::io::Result<()> {
+        let kept = new.iter().map(Task::id).collect::<BTreeSet<_>>();
+        let now = Utc::now();
+        let gone = old
+            .iter()
+            .filter(|x| !kept.contains(x.id()))
+            .map(|x| x.clone().with_removed_at(now))
+            .collect::<Vec<_>>();
+
+        let mut removed = read(&self.removed_path())?;
+        let count = removed.len();
+        removed.retain(|x| !kept.contains(x.id()));
+        if gone.is_empty() && removed.len() == count {
+            return Ok(());
+        }
+        removed.extend(gone);
+
+        let json = serde_json::to_string(&removed)?;
+        write_atomic(
+            &self.removed_path(),
+            |file| file.write_all(json.as_bytes()),
+            || Ok(()),
+        )
+    }
+
+    /// Shift all backups one version back and copy the current file to the first one.
+    fn rotate_backups(&self) -> std::io::Result<()> {
+        if self.backups == 0 || !self.path.exists() {
+            return Ok(());
+        }
+
+        for version in (1..self.backups).rev() {
+            let from = self.backup_path(version);
+            if from.exists() {
+                fs::rename(&from, self.backup_path(version + 1))?;
+            }
+        }
+
+        fs::copy(&self.path, self.backup_path(1)).map(|_| ())
     }
 }
 
@@ -18,30 +155,277 @@ impl crate::repository::Repository for JsonRepository {
     type Err = std::io::Error;
 
     fn all(&self) -> Result<TaskList, Self::Err> {
-        let file_content = match fs::read_to_string(&self.path) {
-            Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::from("[]")), // Empty JSON array
-            x => x,
-        }?;
+        let db = if self.path.exists() {
+            let _lock = self.lock(false)?;
+            self.read()?
+        } else {
+            Vec::new()
+        };
 
-        let db = serde_json::from_str::<Vec<Task>>(&file_content)?;
+        self.read.replace(Some(db.clone()));
         Ok(TaskList::from(db))
     }
 
     fn save(&self, tasks: TaskList) -> Result<(), Self::Err> {
-        let json = serde_json::to_string(&tasks.as_slice())?;
-        let directory = self
-            .path
-            .parent()
-            .expect("Expected a directory for the file");
+        self.create_directory()?;
+
+        let _lock = self.lock(true)?;
+        let current = self.read()?;
+        let tasks = match self.read.borrow().as_deref() {
+            Some(read) if read != current => repository::merge(read, tasks.as_slice(), &current)
+                .map_err(std::io::Error::other)?,
+            _ => tasks,
+        };
+        self.replace(&current, &tasks)?;
+
+        self.read.replace(Some(tasks.as_slice().to_vec()));
+        Ok(())
+    }
 
-        if let Err(err) = fs::metadata(directory) {
-            if err.kind() == ErrorKind::NotFound {
-                fs::create_dir_all(directory)?
-            } else {
-                return Err(err);
+    /// Make the changes of `operations` to the tasks in the file, which is written once while
+    /// it is locked.
+    fn write(&self, operations: &[Operation]) -> Result<(), Self::Err> {
+        self.create_directory()?;
+
+        let _lock = self.lock(true)?;
+        let current = self.read()?;
+        let mut tasks = TaskList::from(current.as_slice());
+        for operation in operations {
+            match operation {
+                Operation::Add(task) => tasks.add(task.clone()),
+                Operation::Edit { new, .. } => {
+                    // Like `update`, a task that doesn't exist is skipped
+                    let _ = tasks.edit(new.clone());
+                }
+                Operation::Remove(task) => tasks.remove(task.id()),
             }
         }
 
-        fs::write(&self.path, &json)
+        self.replace(&current, &tasks)
+    }
+
+    fn removed(&self) -> Result<TaskList, Self::Err> {
+        if !self.removed_path().exists() {
+            return Ok(TaskList::new());
+        }
+
+        // Tasks whose removal was archived but never written are still there
+        let _lock = self.lock(false)?;
+        let tasks = self.read()?;
+        let removed = read(&self.removed_path())?
+            .into_iter()
+            .filter(|x| !tasks.iter().any(|y| y.id() == x.id()))
+            .collect::<Vec<_>>();
+        Ok(TaskList::from(removed))
+    }
+}
+
+/// Read the tasks in the file at `path`, which are none if there is no file.
+fn read(path: &Path) -> std::io::Result<Vec<Task>> {
+    let file_content = match fs::read_to_string(path) {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::from("[]")), // Empty JSON array
+        x => x,
+    }?;
+
+    Ok(serde_json::from_str::<Vec<Task>>(&file_content)?)
+}
+
+/// Replace the file at `path` without ever leaving it half written.
+///
+/// The content is written by `write` to a temporary file in the same directory, which is
+/// flushed to disk and then renamed over `path`. `before_replace` runs right before the
+/// rename. If anything fails the temporary file is removed and `path` is left untouched.
+fn write_atomic<W, B>(path: &Path, write: W, before_replace: B) -> std::io::Result<()>
+where
+    W: FnOnce(&mut File) -> std::io::Result<()>,
+    B: FnOnce() -> std::io::Result<()>,
+{
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    let temp = path.with_file_name(name);
+
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all()
+        })
+        .and_then(|_| before_replace())
+        .and_then(|_| fs::rename(&temp, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+
+    // Make the rename itself durable. Not every platform can open a directory, so this is
+    // a best effort.
+    if let Some(directory) = path.parent() {
+        let _ = File::open(directory).and_then(|x| x.sync_all());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+
+    use super::*;
+    use crate::{history::ChangeKind, repository::Repository, TaskName};
+
+    fn tasks(names: &[&str]) -> TaskList {
+        names
+            .iter()
+            .map(|x| Task::new(TaskName::new(x).unwrap()))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn names(repo: &JsonRepository) -> Vec<String> {
+        repo.all()
+            .unwrap()
+            .iter()
+            .map(|x| x.name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn save_should_replace_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = JsonRepository::new(dir.path().join("today").join("tasks.json"));
+
+        repo.save(tasks(&["First"])).unwrap();
+        repo.save(tasks(&["Second"])).unwrap();
+
+        assert_eq!(names(&repo), vec!["Second"]);
+        assert!(!dir.path().join("today").join(".tasks.json.tmp").exists());
+    }
+
+    #[test]
+    fn save_should_keep_removed_tasks_until_saved_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = JsonRepository::new(dir.path().join("tasks.json"));
+        repo.save(tasks(&["Kept", "Removed"])).unwrap();
+
+        let all = repo.all().unwrap();
+        let kept = all.iter().filter(|x| x.name() == "Kept").cloned();
+        repo.save(TaskList::from(kept.collect::<Vec<_>>())).unwrap();
+
+        let removed = repo.removed().unwrap();
+        assert!(dir.path().join("tasks.removed.json").exists());
+        assert_eq!(removed.as_slice()[0].name(), "Removed");
+        assert_eq!(
+            removed.as_slice()[0].history().last().unwrap().kind,
+            ChangeKind::Removed
+        );
+
+        repo.save(all).unwrap();
+        assert!(repo.removed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_archive_should_keep_previous_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = JsonRepository::new(dir.path().join("tasks.json"));
+        repo.save(tasks(&["Keep me"])).unwrap();
+
+        // A directory where the temporary archive should go makes its write fail.
+        fs::create_dir(dir.path().join(".tasks.removed.json.tmp")).unwrap();
+
+        assert!(repo.save(tasks(&["Lost"])).is_err());
+        assert_eq!(names(&repo), vec!["Keep me"]);
+        assert!(repo.removed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_write_should_keep_previous_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.json");
+        let repo = JsonRepository::new(&path);
+        repo.save(tasks(&["Keep me"])).unwrap();
+
+        let result = write_atomic(
+            &path,
+            |file| {
+                file.write_all(b"[{\"name\": \"Half")?;
+                Err(Error::other("disk full"))
+            },
+            || Ok(()),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(names(&repo), vec!["Keep me"]);
+        assert!(!dir.path().join(".tasks.json.tmp").exists());
+    }
+
+    #[test]
+    fn failed_create_should_keep_previous_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.json");
+        let repo = JsonRepository::new(&path);
+        repo.save(tasks(&["Keep me"])).unwrap();
+
+        // A directory where the temporary file should go makes the write fail.
+        fs::create_dir(dir.path().join(".tasks.json.tmp")).unwrap();
+
+        assert!(repo.save(tasks(&["Lost"])).is_err());
+        assert_eq!(names(&repo), vec!["Keep me"]);
+    }
+
+    #[test]
+    fn save_should_merge_with_changes_from_another_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.json");
+        let first = JsonRepository::new(&path);
+        let second = JsonRepository::new(&path);
+        first.save(tasks(&["Existing"])).unwrap();
+
+        let mut first_tasks = first.all().unwrap();
+        let mut second_tasks = second.all().unwrap();
+        first_tasks.add(Task::new(TaskName::new("First").unwrap()));
+        second_tasks.add(Task::new(TaskName::new("Second").unwrap()));
+        first.save(first_tasks).unwrap();
+        second.save(second_tasks).unwrap();
+
+        let mut merged = names(&first);
+        merged.sort();
+        assert_eq!(merged, vec!["Existing", "First", "Second"]);
+    }
+
+    #[test]
+    fn save_should_fail_given_conflicting_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.json");
+        let first = JsonRepository::new(&path);
+        let second = JsonRepository::new(&path);
+        first.save(tasks(&["Contested"])).unwrap();
+
+        let task = first.all().unwrap().as_slice()[0].clone();
+        second.all().unwrap();
+        first
+            .save(vec![task.clone().with_name(TaskName::new("Ours").unwrap())].into())
+            .unwrap();
+        let result = second.save(vec![task.with_name(TaskName::new("Theirs").unwrap())].into());
+
+        assert!(result.is_err());
+        assert_eq!(names(&first), vec!["Ours"]);
+    }
+
+    #[test]
+    fn save_should_rotate_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.json");
+        let repo = JsonRepository::new(&path).with_backups(2);
+
+        for name in ["One", "Two", "Three", "Four"] {
+            repo.save(tasks(&[name])).unwrap();
+        }
+
+        let backup = |version| JsonRepository::new(repo.backup_path(version));
+        assert_eq!(names(&repo), vec!["Four"]);
+        assert_eq!(names(&backup(1)), vec!["Three"]);
+        assert_eq!(names(&backup(2)), vec!["Two"]);
+        assert!(!repo.backup_path(3).exists());
     }
 }
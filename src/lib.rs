@@ -1,7 +1,9 @@
-#![feature(round_char_boundary)]
-
+pub mod history;
 pub mod partial_config;
+pub mod recurrence;
 pub mod task;
+pub mod timezone;
+pub mod tracking;
 
 pub mod monoid;
 pub mod semigroup;
@@ -11,5 +13,7 @@ pub use task::*;
 pub mod formatter;
 pub mod parser;
 
+pub mod journal;
 pub mod json;
 pub mod repository;
+pub mod sqlite;
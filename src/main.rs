@@ -1,24 +1,40 @@
-#![feature(round_char_boundary)]
-
-use std::{env, path::PathBuf, thread};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    thread,
+};
 
+use anyhow::Context;
 use clap::ArgMatches;
 use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyModifiers};
 use hotwatch::Hotwatch;
 
 use today::{
     combine,
+    formatter::Layout,
+    journal::Journal,
+    json::JsonRepository,
     monoid::{Last, Monoid},
+    parser::query::Query,
     partial_config::{Build, Run, Select},
+    repository::Repository,
     semigroup::Semigroup,
+    sqlite::SqliteRepository,
+    timezone::Zone,
 };
 
 mod app;
 mod cli;
 mod commands;
+mod config;
 mod ui;
 
-use commands::Command;
+use commands::{Command, OutputFormat, Readiness};
+use config::Backend;
+
+const JSON_FILE_NAME: &str = "tasks.json";
+const SQLITE_FILE_NAME: &str = "tasks.db";
+const JOURNAL_FILE_NAME: &str = "journal.jsonl";
 
 today::config!(
     derive(Debug, Default, Clone)
@@ -27,7 +43,17 @@ today::config!(
         data: Last<PathBuf> => PathBuf,
         command: Last<Command> => Command,
         watch_mode: Last<bool> => bool,
+        show_completed: Last<bool> => bool,
+        readiness: Last<Readiness> => Readiness,
+        format: Last<OutputFormat> => OutputFormat,
+        tags: Last<Vec<String>> => Vec<String>,
+        query: Last<Query> => Option<Query>,
         config_only: Option<bool> => bool,
+        list_layout: Last<Layout> => Layout,
+        today_layout: Last<Layout> => Layout,
+        timezone: Last<Zone> => Zone,
+        backups: Last<usize> => usize,
+        backend: Last<Backend> => Backend,
     }
 );
 
@@ -38,7 +64,17 @@ impl AppPaths<Build> {
             data: self.data.get().0.unwrap_or_default().into(),
             command: self.command.get().0.unwrap_or_default().into(),
             watch_mode: self.watch_mode.get().0.unwrap_or_default().into(),
+            show_completed: self.show_completed.get().0.unwrap_or_default().into(),
+            readiness: self.readiness.get().0.unwrap_or_default().into(),
+            format: self.format.get().0.unwrap_or_default().into(),
+            tags: self.tags.get().0.unwrap_or_default().into(),
+            query: self.query.get().0.into(),
             config_only: self.config_only.get().unwrap_or_default().into(),
+            list_layout: self.list_layout.get().0.unwrap_or_default().into(),
+            today_layout: self.today_layout.get().0.unwrap_or_default().into(),
+            timezone: self.timezone.get().0.unwrap_or_default().into(),
+            backups: self.backups.get().0.unwrap_or_default().into(),
+            backend: self.backend.get().0.unwrap_or_default().into(),
         }
     }
 }
@@ -50,7 +86,17 @@ impl AppPaths<Run> {
             data: self.data.into(),
             command: self.command.into(),
             watch_mode: self.watch_mode.into(),
+            show_completed: self.show_completed.into(),
+            readiness: self.readiness.into(),
+            format: self.format.into(),
+            tags: self.tags.into(),
+            query: self.query.into(),
             config_only: self.config_only.into(),
+            list_layout: self.list_layout.into(),
+            today_layout: self.today_layout.into(),
+            timezone: self.timezone.into(),
+            backups: self.backups.into(),
+            backend: self.backend.into(),
         }
     }
 }
@@ -66,8 +112,8 @@ impl std::fmt::Display for AppPaths<Run> {
     }
 }
 
-today::semigroup_default!(AppPaths<Build>: config, data, command, watch_mode, config_only);
-today::monoid_default!(AppPaths<Build>: config, data, command, watch_mode, config_only);
+today::semigroup_default!(AppPaths<Build>: config, data, command, watch_mode, show_completed, readiness, format, tags, query, config_only, list_layout, today_layout, timezone, backups, backend);
+today::monoid_default!(AppPaths<Build>: config, data, command, watch_mode, show_completed, readiness, format, tags, query, config_only, list_layout, today_layout, timezone, backups, backend);
 
 macro_rules! convert_env {
     ($e:expr , $f:expr) => {
@@ -79,6 +125,12 @@ fn read_env() -> anyhow::Result<AppPaths<Build>> {
     Ok(AppPaths {
         config: convert_env!("TODAY_CONFIG_PATH", PathBuf::from),
         data: convert_env!("TODAY_DATA_PATH", PathBuf::from),
+        timezone: env::var("TODAY_TIMEZONE")
+            .ok()
+            .map(|x| x.parse::<Zone>())
+            .transpose()
+            .context("Invalid time zone in TODAY_TIMEZONE")?
+            .into(),
         ..Default::default()
     })
 }
@@ -101,20 +153,77 @@ fn read_xdg() -> anyhow::Result<AppPaths<Build>> {
     })
 }
 
-fn read_args(mut args: ArgMatches) -> AppPaths<Build> {
+/// Read the command line arguments, dates in them are read as wall clock times in `zone`.
+fn read_args(mut args: ArgMatches, zone: Zone) -> AppPaths<Build> {
     let config_only = args.contains_id(cli::ARG_CONFIG).into();
     if let Some((subcommand, matches)) = args.remove_subcommand() {
         let watch_mode = matches
             .try_contains_id(cli::ARG_WATCH_MODE)
             .unwrap_or_default()
+            .then_some(true)
+            .into();
+        let show_completed = matches
+            .try_contains_id(cli::ARG_SHOW_COMPLETED)
+            .unwrap_or_default()
+            .then_some(true)
+            .into();
+        let readiness = if matches
+            .try_contains_id(cli::ARG_BLOCKED)
+            .unwrap_or_default()
+        {
+            Some(Readiness::Blocked)
+        } else if matches.try_contains_id(cli::ARG_READY).unwrap_or_default() {
+            Some(Readiness::Ready)
+        } else {
+            None
+        }
+        .into();
+        let format = matches
+            .try_get_one::<String>(cli::ARG_FORMAT)
+            .ok()
+            .flatten()
+            .map(|x| match x.as_str() {
+                "json" => OutputFormat::Json,
+                "ndjson" => OutputFormat::Ndjson,
+                _ => OutputFormat::Text,
+            })
             .into();
 
-        let command = commands::parser::parse(&subcommand, matches)
+        let projects = matches
+            .try_get_many::<String>(cli::ARG_PROJECT)
+            .ok()
+            .flatten()
+            .into_iter()
+            .flatten()
+            .map(|x| format!("+{}", x.trim_start_matches('+')));
+        let tags = matches
+            .try_get_many::<String>(cli::ARG_TAG)
+            .ok()
+            .flatten()
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(projects)
+            .collect::<Vec<_>>();
+        let tags = (!tags.is_empty()).then_some(tags).into();
+        let query = matches
+            .try_get_one::<Query>(cli::ARG_QUERY)
+            .ok()
+            .flatten()
+            .cloned()
+            .into();
+
+        let command = commands::parser::parse(&subcommand, matches, zone)
             .unwrap_or_default()
             .into();
         AppPaths {
             command,
             watch_mode,
+            show_completed,
+            readiness,
+            format,
+            tags,
+            query,
             config_only,
             ..Default::default()
         }
@@ -126,27 +235,101 @@ fn read_args(mut args: ArgMatches) -> AppPaths<Build> {
     }
 }
 
+/// Open the SQLite database in `data`. The first time it is created the tasks in an
+/// existing `tasks.json` are imported into it.
+fn open_sqlite(data: &Path) -> anyhow::Result<SqliteRepository> {
+    let path = data.join(SQLITE_FILE_NAME);
+    let json = data.join(JSON_FILE_NAME);
+    let import = !path.exists() && json.exists();
+
+    let sqlite = SqliteRepository::open(&path)
+        .with_context(|| format!("Unable to open database {}", path.to_string_lossy()))?;
+
+    if import {
+        let imported = JsonRepository::new(&json)
+            .all()
+            .and_then(|tasks| {
+                let count = tasks.len();
+                sqlite.import(tasks).map(|_| count)
+            })
+            .with_context(|| format!("Unable to import tasks from {}", json.to_string_lossy()));
+
+        match imported {
+            Ok(count) => eprintln!(
+                "Imported {count} tasks from {} into {}",
+                json.to_string_lossy(),
+                path.to_string_lossy()
+            ),
+            Err(err) => {
+                // Without the database the import is tried again next time
+                drop(sqlite);
+                let _ = std::fs::remove_file(&path);
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(sqlite)
+}
+
 fn main() -> anyhow::Result<()> {
     let matches = cli::options();
 
+    let xdg = read_xdg().unwrap_or_default();
+    let env = read_env()?;
+
+    let config_dir = combine! {
+        AppPaths::empty() =>
+            xdg.clone(),
+            env.clone()
+    }
+    .build()
+    .config
+    .get();
+    let file = config::read_file(&config_dir)?;
+
+    // Dates on the command line are parsed with the arguments, so the time zone has to be
+    // known before them.
+    let zone = combine! {
+        AppPaths::empty() =>
+            xdg.clone(),
+            file.clone(),
+            env.clone()
+    }
+    .build()
+    .timezone
+    .get();
+    let args = read_args(matches, zone);
+
     let config = combine! {
         AppPaths::empty() =>
-            read_xdg().unwrap_or_default(),
-            read_env().unwrap_or_default(),
-            read_args(matches)
+            xdg,
+            file,
+            env,
+            args
     }
     .build();
 
-    let mut path = config.data.value().to_owned();
-    path.push("tasks.json");
-    let json = today::json::JsonRepository::new(&path);
-
     let (tx, rx) = std::sync::mpsc::channel();
     let (shutdown_tx, shutdown_rx) = std::sync::mpsc::channel();
-    let watch_mode = config.watch_mode.get();
+    let watch_mode = config.watch_mode.get() && matches!(config.command.value(), Command::Today);
     let config_only = config.config_only.get();
 
-    let mut app = app::App::new(config, json).with_writer(std::io::stdout());
+    let data = config.data.value().to_owned();
+    let (path, app) = match config.backend.get() {
+        Backend::Json => {
+            let path = data.join(JSON_FILE_NAME);
+            let json = JsonRepository::new(&path).with_backups(config.backups.get());
+            (path, app::App::new(config, json))
+        }
+        Backend::Sqlite => {
+            let sqlite = open_sqlite(&data)?;
+            (data.join(SQLITE_FILE_NAME), app::App::new(config, sqlite))
+        }
+    };
+    let mut app = app
+        .with_journal(Journal::new(data.join(JOURNAL_FILE_NAME)))
+        .with_writer(std::io::stdout());
 
     // file_watch is declared outside of the if block because it needs to live a long time.
     // If declared inside the if block it will drop when the if block goes out of scope and
@@ -156,8 +339,21 @@ fn main() -> anyhow::Result<()> {
     if watch_mode && !config_only {
         file_watch = Hotwatch::new().expect("Failed to initialize a notifier");
         let tx_file_changed = tx.clone();
-        file_watch.watch(path, move |_| {
-            let _ = tx_file_changed.send(());
+        // Saving replaces the file with a new one, so the directory is watched rather than
+        // the file itself.
+        let file_name = path.file_name().map(ToOwned::to_owned);
+        let directory = path.parent().map(ToOwned::to_owned).unwrap_or_default();
+        file_watch.watch(directory, move |event| {
+            let changed = match event {
+                hotwatch::Event::Create(x)
+                | hotwatch::Event::Write(x)
+                | hotwatch::Event::Remove(x)
+                | hotwatch::Event::Rename(_, x) => x.file_name() == file_name.as_deref(),
+                _ => false,
+            };
+            if changed {
+                let _ = tx_file_changed.send(());
+            }
         })?;
         app = app
             .with_event_file_changed(rx)
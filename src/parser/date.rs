@@ -0,0 +1,305 @@
+use std::convert::TryFrom;
+
+use chrono::{prelude::*, Duration};
+use thiserror::Error;
+
+/// The most words a date expression can have, `in 3 days at 09:00`.
+const MAX_WORDS: usize = 5;
+
+/// The largest number of units an offset can move a date.
+const MAX_OFFSET: i64 = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DateError {
+    #[error("Invalid date '{0}'")]
+    InvalidDate(String),
+}
+
+/// Parse a date expression relative to `now`.
+///
+/// Accepted expressions are
+/// - absolute dates `2022-06-15`
+/// - `now`, `today`, `tomorrow` and `yesterday`
+/// - weekdays `friday`, the next one after today with `next friday`
+/// - `next week`, `next month` and `next year`
+/// - offsets `in 3 days`, `in 2 weeks`, `+2w`, `-1d`, `+4h` with the units
+///   `m`inutes, `h`ours, `d`ays, `w`eeks, `M`onths and `y`ears
+/// - `end of week`, `end of month` and `end of year`
+///
+/// optionally followed by a time `09:00` or `at 09:00`. A time on its own is today at
+/// that time. Expressions in days or longer are at midnight unless a time is given.
+/// ```
+/// use today::parser::date::parse;
+/// use chrono::prelude::*;
+///
+/// let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(10, 30, 0); // A wednesday
+///
+/// assert_eq!(parse("next friday 9:00", now), Ok(NaiveDate::from_ymd(2022, 6, 17).and_hms(9, 0, 0)));
+/// assert_eq!(parse("+2w", now), Ok(NaiveDate::from_ymd(2022, 6, 29).and_hms(0, 0, 0)));
+/// ```
+pub fn parse(input: &str, now: NaiveDateTime) -> Result<NaiveDateTime, DateError> {
+    let words = input
+        .split_whitespace()
+        .map(|x| {
+            if x.starts_with(['+', '-']) {
+                x.to_owned()
+            } else {
+                x.to_lowercase()
+            }
+        })
+        .collect::<Vec<_>>();
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+
+    expression(&words, now).ok_or_else(|| DateError::InvalidDate(input.trim().to_owned()))
+}
+
+/// Parse the longest date expression at the start of `input`.
+///
+/// Returns the date and the number of bytes of `input` that was used.
+/// ```
+/// use today::parser::date::parse_prefix;
+/// use chrono::prelude::*;
+///
+/// let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(10, 30, 0);
+/// let (date, length) = parse_prefix("in 3 days Call Dave", now).unwrap();
+///
+/// assert_eq!(date, NaiveDate::from_ymd(2022, 6, 18).and_hms(0, 0, 0));
+/// assert_eq!(length, 9);
+/// ```
+pub fn parse_prefix(input: &str, now: NaiveDateTime) -> Option<(NaiveDateTime, usize)> {
+    let word_ends = input
+        .char_indices()
+        .filter(|(i, x)| {
+            !x.is_whitespace()
+                && input[*i + x.len_utf8()..]
+                    .chars()
+                    .next()
+                    .is_none_or(char::is_whitespace)
+        })
+        .map(|(i, x)| i + x.len_utf8())
+        .take(MAX_WORDS)
+        .collect::<Vec<_>>();
+
+    word_ends
+        .into_iter()
+        .rev()
+        .find_map(|end| parse(&input[..end], now).ok().map(|x| (x, end)))
+}
+
+fn expression(words: &[&str], now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let midnight = NaiveTime::from_hms(0, 0, 0);
+
+    // A time on its own
+    if let [time] = words {
+        if let Some(time) = parse_time(time) {
+            return Some(now.date().and_time(time));
+        }
+    }
+
+    let (date, rest) = match words {
+        ["now", rest @ ..] => (now, rest),
+        [word, rest @ ..] if word.starts_with(['+', '-']) => (offset(word, now)?, rest),
+        ["in", count, unit, rest @ ..] => {
+            let count = count.parse::<i64>().ok()?;
+            (add(now, count, unit_of(unit)?)?, rest)
+        }
+        ["end", "of", period, rest @ ..] => (end_of(period, now.date())?.and_time(midnight), rest),
+        ["next", period, rest @ ..] => (next(period, now.date())?.and_time(midnight), rest),
+        [word, rest @ ..] => (day(word, now.date())?.and_time(midnight), rest),
+        [] => return None,
+    };
+
+    match rest {
+        [] => Some(date),
+        ["at", time] | [time] => parse_time(time).map(|x| date.date().and_time(x)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+fn unit_of(word: &str) -> Option<Unit> {
+    match word.trim_end_matches('s') {
+        "minute" | "min" => Some(Unit::Minute),
+        "hour" => Some(Unit::Hour),
+        "day" => Some(Unit::Day),
+        "week" => Some(Unit::Week),
+        "month" => Some(Unit::Month),
+        "year" => Some(Unit::Year),
+        _ => None,
+    }
+}
+
+/// Parse offsets like `+2w` or `-1d`. The case matters to tell `m`inutes from `M`onths.
+fn offset(word: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let unit = match word.chars().last()? {
+        'm' => Unit::Minute,
+        'h' => Unit::Hour,
+        'd' => Unit::Day,
+        'w' => Unit::Week,
+        'M' => Unit::Month,
+        'y' => Unit::Year,
+        _ => return None,
+    };
+    let count = word[..word.len() - 1].parse::<i64>().ok()?;
+
+    add(now, count, unit)
+}
+
+/// Move `now` by `count` units. Units of days or longer end up at midnight.
+fn add(now: NaiveDateTime, count: i64, unit: Unit) -> Option<NaiveDateTime> {
+    // Keeps the durations below from overflowing
+    if count.abs() > MAX_OFFSET {
+        return None;
+    }
+
+    let date = match unit {
+        Unit::Minute => return now.checked_add_signed(Duration::minutes(count)),
+        Unit::Hour => return now.checked_add_signed(Duration::hours(count)),
+        Unit::Day => now.date().checked_add_signed(Duration::days(count)),
+        Unit::Week => now.date().checked_add_signed(Duration::weeks(count)),
+        Unit::Month => add_months(now.date(), count),
+        Unit::Year => add_months(now.date(), count * 12),
+    }?;
+
+    Some(date.and_time(NaiveTime::from_hms(0, 0, 0)))
+}
+
+/// Add months keeping the day of month, or the last day if the month is shorter.
+fn add_months(date: NaiveDate, months: i64) -> Option<NaiveDate> {
+    let month = i64::from(date.year()) * 12 + i64::from(date.month0()) + months;
+    let year = i32::try_from(month.div_euclid(12)).ok()?;
+    let month = month.rem_euclid(12) as u32 + 1;
+
+    (28..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .or_else(|| NaiveDate::from_ymd_opt(year, month, date.day()))
+}
+
+fn end_of(period: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match period {
+        "week" => {
+            Some(today + Duration::days(i64::from(6 - today.weekday().num_days_from_monday())))
+        }
+        "month" => add_months(today.with_day(1)?, 1).map(|x| x.pred()),
+        "year" => NaiveDate::from_ymd_opt(today.year(), 12, 31),
+        _ => None,
+    }
+}
+
+fn next(period: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match period {
+        "week" => Some(today + Duration::weeks(1)),
+        "month" => add_months(today, 1),
+        "year" => add_months(today, 12),
+        _ => {
+            let weekday = period.parse::<Weekday>().ok()?;
+            (1..=7)
+                .map(|x| today + Duration::days(x))
+                .find(|x| x.weekday() == weekday)
+        }
+    }
+}
+
+fn day(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match word {
+        "today" => Some(today),
+        "tomorrow" => Some(today.succ()),
+        "yesterday" => Some(today.pred()),
+        _ => NaiveDate::parse_from_str(word, "%Y-%m-%d")
+            .ok()
+            .or_else(|| {
+                let weekday = word.parse::<Weekday>().ok()?;
+                (0..7)
+                    .map(|x| today + Duration::days(x))
+                    .find(|x| x.weekday() == weekday)
+            }),
+    }
+}
+
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(word, "%H:%M").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    /// Wednesday the 15th of June 2022 at 10:30
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd(2022, 6, 15).and_hms(10, 30, 0)
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(y, m, d).and_hms(h, min, 0)
+    }
+
+    #[test_case("2022-07-01" => at(2022, 7, 1, 0, 0))]
+    #[test_case("2022-07-01 13:45" => at(2022, 7, 1, 13, 45))]
+    #[test_case("now" => at(2022, 6, 15, 10, 30))]
+    #[test_case("today" => at(2022, 6, 15, 0, 0))]
+    #[test_case("Tomorrow 9:00" => at(2022, 6, 16, 9, 0))]
+    #[test_case("tomorrow at 18:15" => at(2022, 6, 16, 18, 15))]
+    #[test_case("yesterday" => at(2022, 6, 14, 0, 0))]
+    #[test_case("17:00" => at(2022, 6, 15, 17, 0))]
+    #[test_case("wednesday" => at(2022, 6, 15, 0, 0))]
+    #[test_case("friday" => at(2022, 6, 17, 0, 0))]
+    #[test_case("next wednesday" => at(2022, 6, 22, 0, 0))]
+    #[test_case("next fri" => at(2022, 6, 17, 0, 0))]
+    #[test_case("next week" => at(2022, 6, 22, 0, 0))]
+    #[test_case("next month" => at(2022, 7, 15, 0, 0))]
+    #[test_case("in 3 days" => at(2022, 6, 18, 0, 0))]
+    #[test_case("in 1 week" => at(2022, 6, 22, 0, 0))]
+    #[test_case("in 2 hours" => at(2022, 6, 15, 12, 30))]
+    #[test_case("+2w" => at(2022, 6, 29, 0, 0))]
+    #[test_case("-1d" => at(2022, 6, 14, 0, 0))]
+    #[test_case("+90m" => at(2022, 6, 15, 12, 0))]
+    #[test_case("+1M" => at(2022, 7, 15, 0, 0))]
+    #[test_case("+1y 08:00" => at(2023, 6, 15, 8, 0))]
+    #[test_case("end of week" => at(2022, 6, 19, 0, 0))]
+    #[test_case("end of month" => at(2022, 6, 30, 0, 0))]
+    #[test_case("end of year at 23:59" => at(2022, 12, 31, 23, 59))]
+    fn parse_expression(input: &str) -> NaiveDateTime {
+        parse(input, now()).unwrap()
+    }
+
+    #[test_case("" ; "empty")]
+    #[test_case("someday")]
+    #[test_case("in three days")]
+    #[test_case("tomorrow 25:00")]
+    #[test_case("+2x")]
+    #[test_case("end of time")]
+    #[test_case("2022-02-30")]
+    fn parse_should_fail_given_invalid_expression(input: &str) {
+        assert!(parse(input, now()).is_err());
+    }
+
+    #[test]
+    fn add_months_should_clamp_to_end_of_month() {
+        let date = NaiveDate::from_ymd(2022, 1, 31);
+
+        assert_eq!(add_months(date, 1), Some(NaiveDate::from_ymd(2022, 2, 28)));
+        assert_eq!(
+            add_months(date, -2),
+            Some(NaiveDate::from_ymd(2021, 11, 30))
+        );
+    }
+
+    #[test_case("tomorrow 9:00 Buy milk" => Some((at(2022, 6, 16, 9, 0), 13)))]
+    #[test_case("in 3 days  Call" => Some((at(2022, 6, 18, 0, 0), 9)))]
+    #[test_case("friday" => Some((at(2022, 6, 17, 0, 0), 6)))]
+    #[test_case("Buy milk tomorrow" => None)]
+    fn parse_prefix_should_use_longest_expression(input: &str) -> Option<(NaiveDateTime, usize)> {
+        parse_prefix(input, now())
+    }
+}
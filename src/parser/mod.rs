@@ -1 +1,3 @@
+pub mod date;
 pub mod program;
+pub mod query;
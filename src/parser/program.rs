@@ -1,9 +1,16 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 use chrono::prelude::*;
 use thiserror::Error;
 
-use crate::{Task, TaskName};
+use super::date;
+use crate::{
+    recurrence::Recurrence,
+    task::{Priority, Tag},
+    timezone::Zone,
+    tracking::Minutes,
+    Task, TaskName,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Program {
@@ -14,38 +21,294 @@ pub enum Program {
         due: Option<DateTime<Utc>>,
     },
     Remove(String),
+    Done(String),
+    Repeat {
+        id: String,
+        repeat: Option<Recurrence>,
+    },
+    Priority {
+        id: String,
+        priority: Priority,
+    },
+    /// Add an item last in the checklist of a task.
+    AddItem {
+        id: String,
+        name: TaskName,
+    },
+    /// Make a task wait for the task with the id `on`.
+    Depend {
+        id: String,
+        on: String,
+    },
+    /// Make a task no longer wait for the task with the id `on`.
+    Undepend {
+        id: String,
+        on: String,
+    },
+    /// Tick off a checklist item, `item` is counted from zero.
+    Tick {
+        id: String,
+        item: usize,
+    },
+    Estimate {
+        id: String,
+        estimate: Option<Minutes>,
+    },
+    /// Change some of the fields of a task, those that are `None` or empty are left as they are.
+    Update {
+        id: String,
+        name: Option<TaskName>,
+        /// `Some(None)` removes the due date.
+        due: Option<Option<DateTime<Utc>>>,
+        add_tags: Vec<Tag>,
+        /// Patterns of the tags to remove, see [`Tag::matches`].
+        remove_tags: Vec<String>,
+    },
     Empty,
 }
 
-#[derive(Debug, Clone, Copy, Error)]
+impl Program {
+    /// The id, or the start of it, of the task the program changes. New tasks have none.
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Program::Edit { id, .. }
+            | Program::Repeat { id, .. }
+            | Program::Priority { id, .. }
+            | Program::AddItem { id, .. }
+            | Program::Depend { id, .. }
+            | Program::Undepend { id, .. }
+            | Program::Tick { id, .. }
+            | Program::Estimate { id, .. }
+            | Program::Update { id, .. }
+            | Program::Remove(id)
+            | Program::Done(id) => Some(id),
+            Program::Add(_) | Program::Empty => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
 pub enum TokenError {
-    #[error("Could not parse task name")]
+    #[error("Expected a task name")]
     InvalidTaskName,
-    #[error("Unable to parse due date")]
+    #[error("Expected a due date like 'YYYY-MM-DD HH:MM', 'Now' or 'tomorrow 9:00'")]
     InvalidDue,
-    #[error("Expected end of string, got '{0}'")]
+    #[error("Expected a repeat rule like daily, weekly:mon,fri, monthly:15 or every:3")]
+    InvalidRepeat,
+    #[error("Expected a priority, one of none, low, medium or high")]
+    InvalidPriority,
+    #[error("Expected the number of a checklist item, counted from 1")]
+    InvalidItem,
+    #[error("Expected an estimate like 1h30m, 2h or 45m")]
+    InvalidEstimate,
+    #[error("Unknown variable, name a date first with 'let start = next monday'")]
+    UnknownVariable,
+    #[error("Expected a definition like 'let start = next monday'")]
+    InvalidDefinition,
+    #[error("Expected due, clear-due, rename, a tag to add like +project or one to remove like -project")]
+    InvalidUpdate,
+    #[error("Expected the end of the line, got '{0}'")]
     ExpectedEOF(char),
-    #[error("Unexpected token '{0}'")]
+    #[error("Unexpected '{0}'")]
     UnexpectedToken(char),
-    #[error("Was expecting a token but got end of string")]
+    #[error("Unexpected end of the line")]
     UnexpectedEOF,
 }
 
-#[derive(Debug, Clone, Copy, Error)]
+/// Where a line failed to parse and why. It is shown with the line and the part of it that
+/// failed underlined.
+/// ```
+/// use today::parser::program::Parser;
+///
+/// let error = Parser::new("new sometime Water plants")
+///     .with_line(3)
+///     .parse()
+///     .unwrap_err();
+///
+/// assert_eq!((error.line(), error.column()), (3, 5));
+/// assert_eq!(
+///     error.to_string().lines().skip(1).collect::<Vec<_>>(),
+///     [" 3 | new sometime Water plants", "   |     ^^^^^^^^"]
+/// );
+/// ```
+#[derive(Debug, Clone, Error)]
 pub struct ParseError {
+    line: usize,
     col: usize,
+    len: usize,
+    text: String,
     source: TokenError,
 }
 
 impl ParseError {
+    /// Where in the line the error is, in bytes from the start of it.
     pub fn position(&self) -> usize {
         self.col
     }
+
+    /// The number of the line, counted from one.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The column of the error, in characters counted from one.
+    pub fn column(&self) -> usize {
+        self.text[..self.col].chars().count() + 1
+    }
+
+    pub fn kind(&self) -> TokenError {
+        self.source
+    }
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "@{}: {:?}", self.col, self.source)
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        writeln!(
+            f,
+            "line {}, column {}: {}",
+            self.line,
+            self.column(),
+            self.source
+        )?;
+        writeln!(f, " {number} | {}", self.text)?;
+        write!(
+            f,
+            " {gutter} | {}{}",
+            " ".repeat(self.column() - 1),
+            "^".repeat(self.len)
+        )
+    }
+}
+
+/// Programs written one per line, like a file of the tasks to add at the start of a sprint.
+///
+/// Lines starting with `#` and blank lines are skipped, and a line ending with `\` continues
+/// on the next line. A date can be named with `let` and used wherever a due date is written,
+/// optionally moved by an offset or set to a time.
+/// ```
+/// use chrono::prelude::*;
+/// use today::{parser::program::{Program, Script}, timezone::Zone};
+///
+/// let script = "# Sprint start
+/// let start = next monday
+///
+/// new $start+1d 09:00 \\
+///     Standup";
+/// let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(10, 30, 0); // A wednesday
+///
+/// let (programs, errors) = Script::new(script)
+///     .with_timezone(Zone::Named(chrono_tz::UTC))
+///     .with_now(now)
+///     .parse();
+///
+/// assert!(errors.is_empty());
+/// match &programs[..] {
+///     [Program::Add(task)] => {
+///         assert_eq!(task.name(), "Standup");
+///         assert_eq!(task.due(), Some(&Utc.ymd(2022, 6, 21).and_hms(9, 0, 0)));
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+pub struct Script<'a> {
+    text: &'a str,
+    now: NaiveDateTime,
+    zone: Zone,
+}
+
+impl<'a> Script<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            now: Zone::Local.now(),
+            zone: Zone::Local,
+        }
+    }
+
+    /// Set the time zone that due dates are written in, see [`Parser::with_timezone`].
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self {
+            now: zone.now(),
+            zone,
+            ..self
+        }
+    }
+
+    /// Set the wall clock time that relative due dates like `tomorrow` are relative to.
+    pub fn with_now(self, now: NaiveDateTime) -> Self {
+        Self { now, ..self }
+    }
+
+    /// Parse the programs of the script. A line that fails to parse is skipped, so that the
+    /// errors of all the lines are found at once.
+    pub fn parse(&self) -> (Vec<Program>, Vec<ParseError>) {
+        let mut programs = Vec::new();
+        let mut errors = Vec::new();
+        let mut variables = HashMap::new();
+
+        for (number, line) in self.lines() {
+            let mut parser = Parser::new(&line)
+                .with_timezone(self.zone)
+                .with_now(self.now)
+                .with_line(number)
+                .with_variables(variables.clone());
+            let definition = match parser.definition() {
+                Ok(x) => x,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+
+            match definition {
+                Some((name, value)) => {
+                    variables.insert(name, value);
+                }
+                None => match parser.parse() {
+                    Ok(program) => programs.push(program),
+                    Err(e) => errors.push(e),
+                },
+            }
+        }
+
+        (programs, errors)
+    }
+
+    /// The lines that aren't blank or comments, with the continued ones joined, and the number
+    /// of the first line of each.
+    fn lines(&self) -> Vec<(usize, String)> {
+        let mut lines = Vec::new();
+        let mut continued: Option<(usize, String)> = None;
+
+        for (number, line) in self.text.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let (first, mut text) = continued.take().unwrap_or((number + 1, String::new()));
+            let line = if text.is_empty() {
+                line
+            } else {
+                line.trim_start()
+            };
+            match line.trim_end().strip_suffix('\\') {
+                Some(line) => {
+                    text.push_str(line.trim_end());
+                    text.push(' ');
+                    continued = Some((first, text));
+                }
+                None => {
+                    text.push_str(line);
+                    lines.push((first, text));
+                }
+            }
+        }
+
+        lines.extend(continued);
+        lines
     }
 }
 
@@ -53,16 +316,61 @@ enum Action {
     Edit,
     Add,
     Remove,
+    Done,
+    Repeat,
+    Priority,
+    Item,
+    Tick,
+    After,
+    Unlink,
+    Estimate,
+    Update,
 }
 
 pub struct Parser<'a> {
     text: &'a str,
     position: usize,
+    line: usize,
+    now: NaiveDateTime,
+    zone: Zone,
+    variables: HashMap<String, NaiveDateTime>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(text: &'a str) -> Self {
-        Self { text, position: 0 }
+        Self {
+            text,
+            position: 0,
+            line: 1,
+            now: Zone::Local.now(),
+            zone: Zone::Local,
+            variables: HashMap::new(),
+        }
+    }
+
+    /// Set the time zone that due dates are written in. This also resets the time that
+    /// relative due dates are relative to, to the current time in `zone`.
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self {
+            now: zone.now(),
+            zone,
+            ..self
+        }
+    }
+
+    /// Set the number of the line the text is on, for telling where errors are.
+    pub fn with_line(self, line: usize) -> Self {
+        Self { line, ..self }
+    }
+
+    /// Set the wall clock time that relative due dates like `tomorrow` are relative to.
+    pub fn with_now(self, now: NaiveDateTime) -> Self {
+        Self { now, ..self }
+    }
+
+    /// Set the dates that can be used as due dates by their names, like `$start`.
+    pub fn with_variables(self, variables: HashMap<String, NaiveDateTime>) -> Self {
+        Self { variables, ..self }
     }
 
     fn skip_whitespace(&mut self) {
@@ -96,9 +404,19 @@ impl<'a> Parser<'a> {
         )
     }
 
+    /// An error for the field at the current position, which is underlined when shown.
     fn create_error(&self, source: TokenError) -> ParseError {
+        let col = self.text.ceil_char_boundary(self.position);
+        let len = self.text[col..]
+            .split(char::is_whitespace)
+            .next()
+            .map_or(0, |x| x.chars().count());
+
         ParseError {
-            col: self.position,
+            line: self.line,
+            col,
+            len: len.max(1),
+            text: self.text.to_owned(),
             source,
         }
     }
@@ -109,7 +427,7 @@ impl<'a> Parser<'a> {
         self.position = index;
 
         match ch {
-            Some(ch) if ch == 'n' => self.add(),
+            Some('n') => self.add(),
             Some(_) => {
                 let id = self.id()?;
                 let action = self.action().unwrap_or(Action::Edit);
@@ -124,16 +442,74 @@ impl<'a> Parser<'a> {
                         _ => unreachable!(),
                     }),
                     Action::Remove => {
-                        self.remove();
+                        self.ignore_rest();
                         Ok(Program::Remove(id))
                     }
+                    Action::Done => {
+                        self.ignore_rest();
+                        Ok(Program::Done(id))
+                    }
+                    Action::Repeat => {
+                        self.skip_whitespace();
+                        let field = self.current_field();
+                        let field_length = field.len();
+                        let repeat = if field == "never" {
+                            self.position += field_length;
+                            None
+                        } else {
+                            Some(self.recurrence()?)
+                        };
+
+                        Ok(Program::Repeat { id, repeat })
+                    }
+                    Action::Priority => {
+                        self.skip_whitespace();
+                        let priority = self.priority()?;
+
+                        Ok(Program::Priority { id, priority })
+                    }
+                    Action::Item => {
+                        let name = self.name()?;
+
+                        Ok(Program::AddItem { id, name })
+                    }
+                    Action::After => {
+                        let on = self.other_id()?;
+
+                        Ok(Program::Depend { id, on })
+                    }
+                    Action::Unlink => {
+                        let on = self.other_id()?;
+
+                        Ok(Program::Undepend { id, on })
+                    }
+                    Action::Tick => {
+                        self.skip_whitespace();
+                        let item = self.item()?;
+
+                        Ok(Program::Tick { id, item })
+                    }
+                    Action::Estimate => {
+                        self.skip_whitespace();
+                        let field = self.current_field();
+                        let field_length = field.len();
+                        let estimate = if field == "none" {
+                            self.position += field_length;
+                            None
+                        } else {
+                            Some(self.estimate()?)
+                        };
+
+                        Ok(Program::Estimate { id, estimate })
+                    }
+                    Action::Update => self.update(id),
                 }
             }
             None => Err(self.create_error(TokenError::UnexpectedEOF)),
         }
     }
 
-    fn remove(&mut self) {
+    fn ignore_rest(&mut self) {
         self.position = self.text.len(); // Ignore the rest of the input
     }
 
@@ -146,16 +522,57 @@ impl<'a> Parser<'a> {
 
     fn add(&mut self) -> Result<Program, ParseError> {
         let action = self.current_field();
+        let action_length = action.len();
 
         if action == "new" {
-            self.position += 3;
+            self.position += action_length;
             self.skip_whitespace();
             let datetime = self.datetime()?;
 
-            self.skip_whitespace();
+            let mut repeat = None;
+            let mut priority = Priority::None;
+            let mut estimate = None;
+            loop {
+                self.skip_whitespace();
+                let start = self.position;
+                let field = self.current_field();
+                let field_length = field.len();
+                let value = match field {
+                    "repeat" => {
+                        self.position += field_length;
+                        self.skip_whitespace();
+                        self.recurrence().map(|x| repeat = Some(x))
+                    }
+                    "priority" => {
+                        self.position += field_length;
+                        self.skip_whitespace();
+                        self.priority().map(|x| priority = x)
+                    }
+                    "estimate" => {
+                        self.position += field_length;
+                        self.skip_whitespace();
+                        self.estimate().map(|x| estimate = Some(x))
+                    }
+                    _ => break,
+                };
+
+                // Without a valid value the keyword is the start of the name, like in
+                // `priority list review`
+                if value.is_err() {
+                    self.position = start;
+                    break;
+                }
+            }
+
             let name = self.name()?;
 
-            Ok(Program::Add(Task::new(name).with_due(datetime)))
+            Ok(Program::Add(
+                Task::new(name)
+                    .with_due(datetime)
+                    .with_repeat(repeat)
+                    .with_priority(priority)
+                    .with_estimate(estimate),
+            ))
         } else {
             let (ch, _) = self.get_char_at(self.position);
             Err(self.create_error(TokenError::UnexpectedToken(ch.unwrap_or_default())))
@@ -176,6 +593,58 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Partial changes like `due tomorrow`, `clear-due`, `+tag`, `-tag` and `rename New name`,
+    /// in any order but with `rename` last since the name is the rest of the line.
+    fn update(&mut self, id: String) -> Result<Program, ParseError> {
+        let mut name = None;
+        let mut due = None;
+        let mut add_tags = Vec::new();
+        let mut remove_tags = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            let field = self.current_field();
+            let field_length = field.len();
+            match field {
+                "" => break,
+                "due" => {
+                    self.position += field_length;
+                    self.skip_whitespace();
+                    due = Some(self.datetime()?);
+                }
+                "clear-due" => {
+                    self.position += field_length;
+                    due = Some(None);
+                }
+                "rename" => {
+                    self.position += field_length;
+                    name = Some(self.name()?);
+                }
+                x => {
+                    match x.strip_prefix('-') {
+                        Some(pattern) if !pattern.is_empty() => {
+                            remove_tags.push(pattern.to_owned())
+                        }
+                        Some(_) => return Err(self.create_error(TokenError::InvalidUpdate)),
+                        None => add_tags.push(
+                            Tag::new(x)
+                                .ok_or_else(|| self.create_error(TokenError::InvalidUpdate))?,
+                        ),
+                    }
+                    self.position += field_length;
+                }
+            }
+        }
+
+        Ok(Program::Update {
+            id,
+            name,
+            due,
+            add_tags,
+            remove_tags,
+        })
+    }
+
     fn id(&mut self) -> Result<String, ParseError> {
         let id = self.current_field().to_owned();
 
@@ -183,6 +652,16 @@ impl<'a> Parser<'a> {
         Ok(id)
     }
 
+    /// The id of another task than the one the instruction is for.
+    fn other_id(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        if self.current_field().is_empty() {
+            return Err(self.create_error(TokenError::UnexpectedEOF));
+        }
+
+        self.id()
+    }
+
     fn action(&mut self) -> Result<Action, ParseError> {
         self.skip_whitespace();
         let action = self.current_field();
@@ -192,6 +671,20 @@ impl<'a> Parser<'a> {
             "edit" => Ok(Action::Edit),
             "new" => Ok(Action::Add),
             "remove" => Ok(Action::Remove),
+            "done" => Ok(Action::Done),
+            "repeat" => Ok(Action::Repeat),
+            "priority" => Ok(Action::Priority),
+            "item" => Ok(Action::Item),
+            "tick" => Ok(Action::Tick),
+            "after" => Ok(Action::After),
+            "unlink" => Ok(Action::Unlink),
+            "estimate" => Ok(Action::Estimate),
+            // The fields are read by the update itself
+            "due" | "clear-due" | "rename" => return Ok(Action::Update),
+            // Offsets like `+2w` are due dates of an edit rather than tags
+            x if x.starts_with(['+', '@', '-']) && date::parse_prefix(x, self.now).is_none() => {
+                return Ok(Action::Update)
+            }
             _ => Err(self.create_error(TokenError::UnexpectedToken(
                 self.get_char_at(self.position).0.unwrap_or_default(),
             ))),
@@ -210,32 +703,124 @@ impl<'a> Parser<'a> {
 
         match current_char {
             None => Err(self.create_error(TokenError::UnexpectedEOF)),
-            Some(ch) if ch == 'N' => {
-                let keyword = self.current_field();
-
-                if keyword == "Now" {
-                    self.position += 3;
-                    Ok(None)
-                } else {
-                    let (ch, _) = self.get_char_at(self.position);
-                    Err(self.create_error(TokenError::UnexpectedToken(ch.unwrap_or_default())))
-                }
+            Some('N') if self.current_field() == "Now" => {
+                self.position += self.current_field().len();
+                Ok(None)
             }
-            Some(_) => {
-                let date = self.date()?;
+            Some(ch) if ch.is_ascii_digit() => {
+                let start = self.position;
+                self.absolute_datetime().or_else(|err| {
+                    self.position = start;
+                    self.date_expression().map_err(|_| err)
+                })
+            }
+            Some('$') => {
+                let due = self.variable()?;
+                Ok(Some(self.zone.to_utc(&due)))
+            }
+            Some(_) => self.date_expression(),
+        }
+    }
 
-                self.skip_whitespace();
+    /// A definition of a variable like `let start = next monday`, `None` if the text is
+    /// something else.
+    fn definition(&mut self) -> Result<Option<(String, NaiveDateTime)>, ParseError> {
+        self.skip_whitespace();
+        let field = self.current_field();
+        let field_length = field.len();
+        if field != "let" {
+            return Ok(None);
+        }
+        self.position += field_length;
+        self.skip_whitespace();
 
-                let time = self.time()?;
+        let name = self.variable_name().to_owned();
+        if name.is_empty() {
+            return Err(self.create_error(TokenError::InvalidDefinition));
+        }
+        self.position += name.len();
+        self.skip_whitespace();
+        if !self.text[self.position..].starts_with('=') {
+            return Err(self.create_error(TokenError::InvalidDefinition));
+        }
+        self.position += 1;
+        self.skip_whitespace();
 
-                Utc.from_utc_date(&date)
-                    .and_time(time)
-                    .map(Some)
-                    .ok_or_else(|| self.create_error(TokenError::InvalidDue))
+        let value = match self.get_char_at(self.position).0 {
+            Some('$') => self.variable()?,
+            _ => self.naive_date_expression()?,
+        };
+
+        self.skip_whitespace();
+        if self.position < self.text.len() {
+            let (ch, _) = self.get_char_at(self.position);
+            return Err(self.create_error(TokenError::ExpectedEOF(ch.unwrap_or_default())));
+        }
+        Ok(Some((name, value)))
+    }
+
+    fn variable_name(&self) -> &str {
+        let rest = &self.text[self.position..];
+        let length = rest
+            .find(|x: char| !(x.is_alphanumeric() || x == '_'))
+            .unwrap_or(rest.len());
+
+        &rest[..length]
+    }
+
+    /// The date of a variable like `$start`. An offset or time directly after it is relative
+    /// to the date, like `$start+1d 09:00`.
+    fn variable(&mut self) -> Result<NaiveDateTime, ParseError> {
+        self.position += 1;
+        let name = self.variable_name();
+        let length = name.len();
+        let date = match self.variables.get(name) {
+            Some(x) => *x,
+            None => {
+                self.position -= 1;
+                return Err(self.create_error(TokenError::UnknownVariable));
+            }
+        };
+        self.position += length;
+
+        let end = self.position;
+        self.skip_whitespace();
+        match date::parse_prefix(&self.text[self.position..], date) {
+            Some((date, length)) => {
+                self.position += length;
+                Ok(date)
+            }
+            None => {
+                self.position = end;
+                Ok(date)
             }
         }
     }
 
+    fn absolute_datetime(&mut self) -> Result<Option<DateTime<Utc>>, ParseError> {
+        let date = self.date()?;
+
+        self.skip_whitespace();
+
+        let time = self.time()?;
+
+        Ok(Some(self.zone.to_utc(&date.and_time(time))))
+    }
+
+    /// A relative date like `tomorrow 9:00`, see [`date::parse`].
+    fn date_expression(&mut self) -> Result<Option<DateTime<Utc>>, ParseError> {
+        let due = self.naive_date_expression()?;
+        Ok(Some(self.zone.to_utc(&due)))
+    }
+
+    fn naive_date_expression(&mut self) -> Result<NaiveDateTime, ParseError> {
+        let (due, length) = date::parse_prefix(&self.text[self.position..], self.now)
+            .ok_or_else(|| self.create_error(TokenError::InvalidDue))?;
+
+        self.position += length;
+        Ok(due)
+    }
+
     fn parse_type<T: FromStr>(&mut self, len: usize) -> Result<T, ParseError> {
         let value = self.text[self.position..self.position + len]
             .parse::<T>()
@@ -306,6 +891,49 @@ impl<'a> Parser<'a> {
             .ok_or_else(|| self.create_error(TokenError::InvalidDue))
     }
 
+    fn recurrence(&mut self) -> Result<Recurrence, ParseError> {
+        let rule = self
+            .current_field()
+            .parse::<Recurrence>()
+            .map_err(|_| self.create_error(TokenError::InvalidRepeat))?;
+
+        self.position += self.current_field().len();
+        Ok(rule)
+    }
+
+    fn priority(&mut self) -> Result<Priority, ParseError> {
+        let priority = self
+            .current_field()
+            .parse::<Priority>()
+            .map_err(|_| self.create_error(TokenError::InvalidPriority))?;
+
+        self.position += self.current_field().len();
+        Ok(priority)
+    }
+
+    fn estimate(&mut self) -> Result<Minutes, ParseError> {
+        let estimate = self
+            .current_field()
+            .parse::<Minutes>()
+            .map_err(|_| self.create_error(TokenError::InvalidEstimate))?;
+
+        self.position += self.current_field().len();
+        Ok(estimate)
+    }
+
+    /// The number of a checklist item as it is shown, counted from one.
+    fn item(&mut self) -> Result<usize, ParseError> {
+        let item = self
+            .current_field()
+            .parse::<usize>()
+            .ok()
+            .and_then(|x| x.checked_sub(1))
+            .ok_or_else(|| self.create_error(TokenError::InvalidItem))?;
+
+        self.position += self.current_field().len();
+        Ok(item)
+    }
+
     fn name(&mut self) -> Result<TaskName, ParseError> {
         self.skip_whitespace();
         let name = TaskName::new(&self.text[self.position..])
@@ -322,6 +950,10 @@ mod tests {
 
     use test_case::test_case;
 
+    fn utc() -> Zone {
+        Zone::Named(chrono_tz::UTC)
+    }
+
     #[test]
     fn time_should_parse_valid_time() {
         let time = "12:43";
@@ -356,7 +988,7 @@ mod tests {
     #[test]
     fn datetime_should_parse_valid_input() {
         let datetime = "2022-04-27 9:43";
-        let mut parser = Parser::new(datetime);
+        let mut parser = Parser::new(datetime).with_timezone(utc());
 
         let result = parser.datetime().unwrap();
 
@@ -364,6 +996,23 @@ mod tests {
         assert_eq!(result, Some(Utc.ymd(2022, 4, 27).and_hms(9, 43, 0)));
     }
 
+    #[test]
+    fn datetime_should_interpret_input_in_timezone() {
+        let zone = Zone::Named(chrono_tz::America::New_York);
+        let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(22, 0, 0);
+
+        let mut absolute = Parser::new("2022-06-15 21:30").with_timezone(zone);
+        let mut relative = Parser::new("tomorrow 9:00")
+            .with_timezone(zone)
+            .with_now(now);
+
+        let absolute = absolute.datetime().unwrap();
+        let relative = relative.datetime().unwrap();
+
+        assert_eq!(absolute, Some(Utc.ymd(2022, 6, 16).and_hms(1, 30, 0)));
+        assert_eq!(relative, Some(Utc.ymd(2022, 6, 16).and_hms(13, 0, 0)));
+    }
+
     #[test]
     fn datetime_should_parse_given_now() {
         let mut parser = Parser::new("Now");
@@ -373,6 +1022,35 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test_case("tomorrow 9:00 Breakfast" => (Utc.ymd(2022, 6, 16).and_hms(9, 0, 0), 13))]
+    #[test_case("Next friday Lunch" => (Utc.ymd(2022, 6, 17).and_hms(0, 0, 0), 11))]
+    #[test_case("10:00 Meeting" => (Utc.ymd(2022, 6, 15).and_hms(10, 0, 0), 5))]
+    #[test_case("2022-07-01 Party" => (Utc.ymd(2022, 7, 1).and_hms(0, 0, 0), 10))]
+    fn datetime_should_parse_relative_dates(input: &str) -> (DateTime<Utc>, usize) {
+        let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(8, 0, 0);
+        let mut parser = Parser::new(input).with_timezone(utc()).with_now(now);
+
+        let result = parser.datetime().unwrap().unwrap();
+
+        (result, parser.position)
+    }
+
+    #[test]
+    fn parse_should_add_task_with_relative_due() {
+        let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(8, 0, 0);
+        let mut parser = Parser::new("new in 2 days at 14:00 Dentist")
+            .with_timezone(utc())
+            .with_now(now);
+
+        match parser.parse().unwrap() {
+            Program::Add(task) => {
+                assert_eq!(task.name(), "Dentist");
+                assert_eq!(task.due(), Some(&Utc.ymd(2022, 6, 17).and_hms(14, 0, 0)));
+            }
+            _ => unreachable!(),
+        }
+    }
+
     #[test_case("   garbage", 0, 3)]
     #[test_case("garbage   for life", 7, 10)]
     fn skip_whitespace_should_advance_position(input: &str, start: usize, end: usize) {
@@ -406,7 +1084,8 @@ mod tests {
 
     #[test]
     fn add_should_create_new_task() {
-        let mut parser = Parser::new("new 2022-12-24 00:00 It's Christmas everybody");
+        let mut parser =
+            Parser::new("new 2022-12-24 00:00 It's Christmas everybody").with_timezone(utc());
 
         let result = parser.add().unwrap();
 
@@ -415,13 +1094,13 @@ mod tests {
                 assert_eq!(task.name(), "It's Christmas everybody");
                 assert_eq!(task.due(), Some(&Utc.ymd(2022, 12, 24).and_hms(0, 0, 0)));
             }
-            _ => assert!(false),
+            _ => unreachable!(),
         }
     }
 
     #[test]
     fn parse_should_parse_new_given_valid_input() {
-        let mut parser = Parser::new("new 2022-05-04 12:00 4th of july lunch");
+        let mut parser = Parser::new("new 2022-05-04 12:00 4th of july lunch").with_timezone(utc());
 
         let result = parser.parse().unwrap();
 
@@ -430,13 +1109,14 @@ mod tests {
                 assert_eq!(task.name(), "4th of july lunch");
                 assert_eq!(task.due(), Some(&Utc.ymd(2022, 5, 4).and_hms(12, 0, 0)));
             }
-            _ => assert!(false),
+            _ => unreachable!(),
         }
     }
 
     #[test]
     fn parse_should_parse_edit_given_valid_input() {
-        let mut parser = Parser::new("4df78 2022-05-04 18:00 4th july dinner, not lunch");
+        let mut parser =
+            Parser::new("4df78 2022-05-04 18:00 4th july dinner, not lunch").with_timezone(utc());
 
         let result = parser.parse().unwrap();
 
@@ -446,13 +1126,14 @@ mod tests {
                 assert_eq!(name, "4th july dinner, not lunch");
                 assert_eq!(due, Some(Utc.ymd(2022, 5, 4).and_hms(18, 0, 0)));
             }
-            _ => assert!(false),
+            _ => unreachable!(),
         }
     }
 
     #[test]
     fn parse_should_parse_edit_given_valid_input_with_action() {
-        let mut parser = Parser::new("4df78 edit 2022-05-04 18:00 4th july dinner, not lunch");
+        let mut parser = Parser::new("4df78 edit 2022-05-04 18:00 4th july dinner, not lunch")
+            .with_timezone(utc());
 
         let result = parser.parse().unwrap();
 
@@ -462,7 +1143,7 @@ mod tests {
                 assert_eq!(name, "4th july dinner, not lunch");
                 assert_eq!(due, Some(Utc.ymd(2022, 5, 4).and_hms(18, 0, 0)));
             }
-            _ => assert!(false),
+            _ => unreachable!(),
         }
     }
 
@@ -476,10 +1157,239 @@ mod tests {
             Program::Remove(id) => {
                 assert_eq!(id, "4df78");
             }
-            _ => assert!(false),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parse_should_parse_done_given_valid_input() {
+        let mut parser = Parser::new("4df78 done");
+
+        let result = parser.parse().unwrap();
+
+        assert_eq!(result, Program::Done(String::from("4df78")));
+    }
+
+    #[test]
+    fn parse_should_parse_new_with_repeat() {
+        let mut parser =
+            Parser::new("new 2022-05-02 09:00 repeat weekly:mon Standup").with_timezone(utc());
+
+        let result = parser.parse().unwrap();
+
+        match result {
+            Program::Add(task) => {
+                assert_eq!(task.name(), "Standup");
+                assert_eq!(task.repeat(), Some(&Recurrence::Weekly(vec![Weekday::Mon])));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test_case("4df78 repeat daily" => Program::Repeat { id: "4df78".to_owned(), repeat: Some(Recurrence::Daily) })]
+    #[test_case("4df78 repeat never" => Program::Repeat { id: "4df78".to_owned(), repeat: None })]
+    fn parse_should_parse_repeat_action(input: &str) -> Program {
+        let mut parser = Parser::new(input);
+
+        parser.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_should_parse_new_with_priority_and_repeat() {
+        let mut parser = Parser::new("new Now priority high repeat daily Check mail");
+
+        let result = parser.parse().unwrap();
+
+        match result {
+            Program::Add(task) => {
+                assert_eq!(task.name(), "Check mail");
+                assert_eq!(task.priority(), Priority::High);
+                assert_eq!(task.repeat(), Some(&Recurrence::Daily));
+            }
+            _ => unreachable!(),
         }
     }
 
+    #[test]
+    fn parse_should_parse_priority_action() {
+        let mut parser = Parser::new("4df78 priority low");
+
+        let result = parser.parse().unwrap();
+
+        assert_eq!(
+            result,
+            Program::Priority {
+                id: "4df78".to_owned(),
+                priority: Priority::Low
+            }
+        );
+    }
+
+    #[test_case("4df78 item Build binaries" => Program::AddItem { id: "4df78".to_owned(), name: TaskName::new("Build binaries").unwrap() })]
+    #[test_case("4df78 tick 2" => Program::Tick { id: "4df78".to_owned(), item: 1 })]
+    #[test_case("4df78 after 1a2b3" => Program::Depend { id: "4df78".to_owned(), on: "1a2b3".to_owned() })]
+    #[test_case("4df78 unlink 1a2b3" => Program::Undepend { id: "4df78".to_owned(), on: "1a2b3".to_owned() })]
+    fn parse_should_parse_checklist_and_dependency_actions(input: &str) -> Program {
+        let mut parser = Parser::new(input);
+
+        parser.parse().unwrap()
+    }
+
+    #[test_case("4df78 tick 0")]
+    #[test_case("4df78 tick first")]
+    #[test_case("4df78 item   ")]
+    #[test_case("4df78 after")]
+    #[test_case("4df78 after 1a2b3 3c4d5")]
+    fn parse_should_fail_given_invalid_checklist_or_dependency_action(input: &str) {
+        let mut parser = Parser::new(input);
+
+        assert!(parser.parse().is_err());
+    }
+
+    #[test_case("4df78 estimate 1h30m" => Program::Estimate { id: "4df78".to_owned(), estimate: Some(Minutes::new(90)) })]
+    #[test_case("4df78 estimate none" => Program::Estimate { id: "4df78".to_owned(), estimate: None })]
+    fn parse_should_parse_estimate_action(input: &str) -> Program {
+        let mut parser = Parser::new(input);
+
+        parser.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_should_parse_partial_updates() {
+        let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(10, 30, 0);
+        let parse = |x| {
+            Parser::new(x)
+                .with_timezone(utc())
+                .with_now(now)
+                .parse()
+                .unwrap()
+        };
+        let update = |name: Option<&str>, due, add: &[&str], remove: &[&str]| Program::Update {
+            id: "4df78".to_owned(),
+            name: name.and_then(TaskName::new),
+            due,
+            add_tags: add.iter().filter_map(|x| Tag::new(x)).collect(),
+            remove_tags: remove.iter().map(|x| x.to_string()).collect(),
+        };
+
+        assert_eq!(
+            parse("4df78 due tomorrow 9:00"),
+            update(
+                None,
+                Some(Some(Utc.ymd(2022, 6, 16).and_hms(9, 0, 0))),
+                &[],
+                &[]
+            )
+        );
+        assert_eq!(
+            parse("4df78 rename Call the bank"),
+            update(Some("Call the bank"), None, &[], &[])
+        );
+        assert_eq!(
+            parse("4df78 +work @phone -home"),
+            update(None, None, &["+work", "@phone"], &["home"])
+        );
+        assert_eq!(
+            parse("4df78 clear-due -@phone rename Call Mom"),
+            update(Some("Call Mom"), Some(None), &[], &["@phone"])
+        );
+    }
+
+    #[test_case("4df78 +work later" ; "unknown word")]
+    #[test_case("4df78 due" ; "missing due date")]
+    #[test_case("4df78 rename" ; "missing name")]
+    #[test_case("4df78 - work" ; "lone dash")]
+    fn parse_should_fail_given_invalid_update(input: &str) {
+        assert!(Parser::new(input).parse().is_err());
+    }
+
+    #[test]
+    fn parse_should_read_offset_as_due_date_of_edit() {
+        let result = Parser::new("4df78 +2w Renew passport").parse().unwrap();
+
+        assert!(matches!(result, Program::Edit { due: Some(_), .. }));
+    }
+
+    #[test]
+    fn script_should_skip_comments_and_join_continued_lines() {
+        let text = "# Groceries\n\n  # for the week\nnew Now Buy \\\n    milk\n4df78 \\\n# between\ndone\n";
+
+        let (programs, errors) = Script::new(text).with_timezone(utc()).parse();
+
+        assert!(errors.is_empty());
+        assert!(matches!(&programs[0], Program::Add(x) if x.name() == "Buy milk"));
+        assert_eq!(programs[1..], [Program::Done("4df78".to_owned())]);
+    }
+
+    #[test]
+    fn script_should_use_dates_of_variables() {
+        let now = NaiveDate::from_ymd(2022, 6, 15).and_hms(10, 30, 0); // A wednesday
+        let text = "let start = next monday\nlet end=$start+4d\nnew $start Plan\nnew $end 16:00 Demo\n4df78 due $start+1d 09:00\n";
+
+        let (programs, errors) = Script::new(text).with_timezone(utc()).with_now(now).parse();
+
+        assert!(errors.is_empty());
+        let dues = programs
+            .iter()
+            .map(|x| match x {
+                Program::Add(task) => task.due().copied(),
+                Program::Update { due, .. } => due.flatten(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dues,
+            [
+                Some(Utc.ymd(2022, 6, 20).and_hms(0, 0, 0)),
+                Some(Utc.ymd(2022, 6, 24).and_hms(16, 0, 0)),
+                Some(Utc.ymd(2022, 6, 21).and_hms(9, 0, 0)),
+            ]
+        );
+    }
+
+    #[test_case("let = next monday" => (1, TokenError::InvalidDefinition))]
+    #[test_case("let start next monday" => (1, TokenError::InvalidDefinition))]
+    #[test_case("let start = sometime" => (1, TokenError::InvalidDue))]
+    #[test_case("\nnew $start Plan" => (2, TokenError::UnknownVariable))]
+    fn script_should_fail_given_invalid_variable(input: &str) -> (usize, TokenError) {
+        let (_, errors) = Script::new(input).with_timezone(utc()).parse();
+
+        (errors[0].line(), errors[0].kind())
+    }
+
+    #[test_case("new Now priority list review" => "priority list review")]
+    #[test_case("new Now estimate taxes" => "estimate taxes")]
+    #[test_case("new Now repeat after me" => "repeat after me")]
+    #[test_case("new Now priority high repeat the lesson" => "repeat the lesson")]
+    fn parse_should_read_keyword_without_value_as_name(input: &str) -> String {
+        match Parser::new(input).parse().unwrap() {
+            Program::Add(task) => task.name().to_owned(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parse_should_parse_new_with_estimate() {
+        let mut parser = Parser::new("new Now estimate 45m Review pull request");
+
+        let result = parser.parse().unwrap();
+
+        match result {
+            Program::Add(task) => {
+                assert_eq!(task.name(), "Review pull request");
+                assert_eq!(task.estimate(), Some(Minutes::new(45)));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parse_should_fail_given_invalid_repeat_rule() {
+        let mut parser = Parser::new("4df78 repeat sometimes");
+
+        assert!(parser.parse().is_err());
+    }
+
     #[test]
     fn parse_should_ignore_empty_input() {
         let mut parser = Parser::new("    ");
@@ -488,4 +1398,47 @@ mod tests {
 
         assert_eq!(result, Program::Empty);
     }
+
+    #[test_case("4df78 repeat sometimes" => (1, 14, TokenError::InvalidRepeat))]
+    #[test_case("4df78 priority urgent" => (1, 16, TokenError::InvalidPriority))]
+    #[test_case("ŝŝŝŝŝ estimate soon" => (1, 16, TokenError::InvalidEstimate))]
+    #[test_case("4df78 priority" => (1, 15, TokenError::InvalidPriority))]
+    fn parse_error_should_point_at_failed_field(input: &str) -> (usize, usize, TokenError) {
+        let error = Parser::new(input).parse().unwrap_err();
+
+        (error.line(), error.column(), error.kind())
+    }
+
+    #[test]
+    fn parse_error_should_underline_field_in_line() {
+        let error = Parser::new("4df78 priority urgent")
+            .with_line(12)
+            .parse()
+            .unwrap_err();
+
+        let lines = error.to_string();
+        let lines = lines.lines().collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "line 12, column 16: Expected a priority, one of none, low, medium or high",
+                " 12 | 4df78 priority urgent",
+                "    |                ^^^^^^",
+            ]
+        );
+    }
+
+    #[test]
+    fn script_should_report_all_failed_lines() {
+        let text = "new Now Water plants\n4df78 priority urgent\n\n4df78 done\nnew sometime x\n";
+
+        let (programs, errors) = Script::new(text).with_timezone(utc()).parse();
+
+        assert_eq!(programs.len(), 2);
+        assert_eq!(
+            errors.iter().map(ParseError::line).collect::<Vec<_>>(),
+            [2, 5]
+        );
+    }
 }
@@ -0,0 +1,517 @@
+use std::str::FromStr;
+
+use chrono::prelude::*;
+use thiserror::Error;
+
+use super::date;
+use crate::{task::Priority, timezone::Zone, Task};
+
+/// A filter over tasks.
+///
+/// Terms can be combined with `and`, `or`, `not` and parentheses. Terms next to each
+/// other without an operator are combined with `and`.
+///
+/// | Term                | Matches tasks that                                   |
+/// |---------------------|------------------------------------------------------|
+/// | `done`              | are completed                                        |
+/// | `overdue`           | are not completed and due before now                 |
+/// | `today`             | are due today or earlier                             |
+/// | `due:<2022-11-01`   | are due before the date, also `<=`, `>`, `>=` and `=`|
+/// | `due:<+2w`          | are due before a relative date, see [`date::parse`]  |
+/// | `tag:work`          | have the tag, see [`crate::task::Tag::matches`]       |
+/// | `project:work`      | have the tag `+work`                                 |
+/// | `priority:>=medium` | have the priority, also `<`, `<=`, `>` and `=`       |
+/// | `name~"invoice"`    | have the text in their name, ignoring case           |
+///
+/// Tasks that should be done as soon as possible are due now. Relative dates are
+/// resolved when the query is matched rather than when it is parsed.
+/// ```
+/// use today::{parser::query::Query, timezone::Zone, Task, TaskName};
+/// use chrono::prelude::*;
+///
+/// let query = "name~\"invoice\" and not done".parse::<Query>().unwrap();
+/// let task = Task::new(TaskName::new("Send invoice to ACME").unwrap());
+///
+/// assert!(query.matches(&task, Utc::now(), Zone::Local));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Done,
+    Overdue,
+    Today,
+    Due(Comparison, String),
+    Tag(String),
+    Priority(Comparison, Priority),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn compare<T: Ord>(&self, left: &T, right: &T) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Greater => left > right,
+        }
+    }
+}
+
+impl Query {
+    /// Returns true if `task` satisfies the query at the time `now`. Dates are compared
+    /// by the wall clock in the time zone of the task, or else in `zone`.
+    pub fn matches(&self, task: &Task, now: DateTime<Utc>, zone: Zone) -> bool {
+        let zone = task.zone_or(zone);
+        let local_now = zone.from_utc(&now);
+        let due = zone.from_utc(&task.due().copied().unwrap_or(now));
+
+        match self {
+            Query::And(left, right) => {
+                left.matches(task, now, zone) && right.matches(task, now, zone)
+            }
+            Query::Or(left, right) => {
+                left.matches(task, now, zone) || right.matches(task, now, zone)
+            }
+            Query::Not(query) => !query.matches(task, now, zone),
+            Query::Done => task.is_completed(),
+            Query::Overdue => !task.is_completed() && task.due().is_some_and(|x| *x < now),
+            Query::Today => due.date() <= local_now.date(),
+            Query::Due(comparison, date) => date::parse(date, local_now)
+                .is_ok_and(|date| comparison.compare(&due.date(), &date.date())),
+            Query::Tag(tag) => task.has_tag(tag),
+            Query::Priority(comparison, priority) => comparison.compare(&task.priority(), priority),
+            Query::Name(text) => task.name().to_lowercase().contains(&text.to_lowercase()),
+        }
+    }
+
+    /// Returns true if the query says anything about tasks being completed.
+    pub fn references_done(&self) -> bool {
+        match self {
+            Query::And(left, right) | Query::Or(left, right) => {
+                left.references_done() || right.references_done()
+            }
+            Query::Not(query) => query.references_done(),
+            Query::Done => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QueryParser::new(s).parse()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QueryTokenError {
+    #[error("Was expecting a term but got end of query")]
+    UnexpectedEOF,
+    #[error("Unexpected '{0}'")]
+    UnexpectedToken(String),
+    #[error("Unknown field '{0}'")]
+    UnknownField(String),
+    #[error("Unknown keyword '{0}'")]
+    UnknownKeyword(String),
+    #[error("Invalid date '{0}'")]
+    InvalidDate(String),
+    #[error("Invalid priority '{0}'")]
+    InvalidPriority(String),
+    #[error("Missing value for field '{0}'")]
+    MissingValue(String),
+    #[error("Missing closing '\"'")]
+    UnterminatedString,
+    #[error("Missing closing ')'")]
+    UnclosedParenthesis,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub struct QueryError {
+    col: usize,
+    source: QueryTokenError,
+}
+
+impl QueryError {
+    pub fn position(&self) -> usize {
+        self.col
+    }
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@{}: {}", self.col, self.source)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Term(String),
+}
+
+struct QueryParser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    len: usize,
+}
+
+impl QueryParser {
+    fn new(text: &str) -> Self {
+        Self {
+            tokens: tokenize(text),
+            position: 0,
+            len: text.len(),
+        }
+    }
+
+    fn parse(&mut self) -> Result<Query, QueryError> {
+        self.check_strings()?;
+        let query = self.or()?;
+
+        match self.tokens.get(self.position) {
+            None => Ok(query),
+            Some((col, Token::Close)) => {
+                Err(error(*col, QueryTokenError::UnexpectedToken(")".into())))
+            }
+            Some((col, Token::Open)) => {
+                Err(error(*col, QueryTokenError::UnexpectedToken("(".into())))
+            }
+            Some((col, Token::Term(x))) => {
+                Err(error(*col, QueryTokenError::UnexpectedToken(x.clone())))
+            }
+        }
+    }
+
+    fn check_strings(&self) -> Result<(), QueryError> {
+        for (col, token) in &self.tokens {
+            if let Token::Term(term) = token {
+                if term.chars().filter(|x| *x == '"').count() % 2 == 1 {
+                    return Err(error(
+                        *col + term.len(),
+                        QueryTokenError::UnterminatedString,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some((_, Token::Term(x))) if x == keyword)
+    }
+
+    fn or(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.and()?;
+
+        while self.peek_keyword("or") {
+            self.position += 1;
+            let right = self.and()?;
+            query = Query::Or(Box::new(query), Box::new(right));
+        }
+
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.not()?;
+
+        loop {
+            if self.peek_keyword("and") {
+                self.position += 1;
+            } else if self.peek_keyword("or")
+                || matches!(
+                    self.tokens.get(self.position),
+                    None | Some((_, Token::Close))
+                )
+            {
+                break;
+            }
+
+            let right = self.not()?;
+            query = Query::And(Box::new(query), Box::new(right));
+        }
+
+        Ok(query)
+    }
+
+    fn not(&mut self) -> Result<Query, QueryError> {
+        if self.peek_keyword("not") {
+            self.position += 1;
+            Ok(Query::Not(Box::new(self.not()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<Query, QueryError> {
+        let (col, token) = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| error(self.len, QueryTokenError::UnexpectedEOF))?;
+        self.position += 1;
+
+        match token {
+            Token::Open => {
+                let query = self.or()?;
+                match self.tokens.get(self.position) {
+                    Some((_, Token::Close)) => {
+                        self.position += 1;
+                        Ok(query)
+                    }
+                    _ => Err(error(col, QueryTokenError::UnclosedParenthesis)),
+                }
+            }
+            Token::Close => Err(error(col, QueryTokenError::UnexpectedToken(")".into()))),
+            Token::Term(term) => term_query(col, &term),
+        }
+    }
+}
+
+fn error(col: usize, source: QueryTokenError) -> QueryError {
+    QueryError { col, source }
+}
+
+/// Split the query into parentheses and terms, keeping the column of each token.
+/// Whitespace inside double quotes is part of the term.
+fn tokenize(text: &str) -> Vec<(usize, Token)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '"' => {
+                quoted = !quoted;
+                start.get_or_insert(i);
+            }
+            _ if quoted => {}
+            '(' | ')' => {
+                if let Some(s) = start.take() {
+                    tokens.push((s, Token::Term(text[s..i].to_owned())));
+                }
+                let token = if ch == '(' { Token::Open } else { Token::Close };
+                tokens.push((i, token));
+            }
+            x if x.is_whitespace() => {
+                if let Some(s) = start.take() {
+                    tokens.push((s, Token::Term(text[s..i].to_owned())));
+                }
+            }
+            _ => {
+                start.get_or_insert(i);
+            }
+        }
+    }
+
+    if let Some(s) = start {
+        tokens.push((s, Token::Term(text[s..].to_owned())));
+    }
+
+    tokens
+}
+
+fn term_query(col: usize, term: &str) -> Result<Query, QueryError> {
+    let separator = term.find([':', '~']);
+    let (field, value) = match separator {
+        None => {
+            return match term {
+                "done" => Ok(Query::Done),
+                "overdue" => Ok(Query::Overdue),
+                "today" => Ok(Query::Today),
+                "and" | "or" => Err(error(col, QueryTokenError::UnexpectedToken(term.into()))),
+                _ => Err(error(col, QueryTokenError::UnknownKeyword(term.into()))),
+            }
+        }
+        Some(index) => (&term[..index], &term[index + 1..]),
+    };
+    let value_col = col + field.len() + 1;
+
+    if value.is_empty() {
+        return Err(error(
+            value_col,
+            QueryTokenError::MissingValue(field.into()),
+        ));
+    }
+
+    let unquoted = value.trim_matches('"');
+    match field {
+        "due" => {
+            let (comparison, due) = comparison(value);
+            let due = due.trim_matches('"');
+            date::parse(due, Utc::now().naive_utc())
+                .map(|_| Query::Due(comparison, due.to_owned()))
+                .map_err(|_| error(value_col, QueryTokenError::InvalidDate(due.into())))
+        }
+        "priority" => {
+            let (comparison, priority) = comparison(value);
+            priority
+                .parse::<Priority>()
+                .map(|x| Query::Priority(comparison, x))
+                .map_err(|_| error(value_col, QueryTokenError::InvalidPriority(priority.into())))
+        }
+        "tag" => Ok(Query::Tag(unquoted.to_owned())),
+        "project" => Ok(Query::Tag(format!("+{}", unquoted.trim_start_matches('+')))),
+        "name" => Ok(Query::Name(unquoted.to_owned())),
+        _ => Err(error(col, QueryTokenError::UnknownField(field.into()))),
+    }
+}
+
+fn comparison(value: &str) -> (Comparison, &str) {
+    [
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ]
+    .iter()
+    .find_map(|(prefix, comparison)| value.strip_prefix(prefix).map(|x| (*comparison, x)))
+    .unwrap_or((Comparison::Equal, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{task::Tag, TaskName};
+    use test_case::test_case;
+
+    fn parse(input: &str) -> Query {
+        input.parse().unwrap()
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2022, 6, 15).and_hms(12, 0, 0)
+    }
+
+    fn utc() -> Zone {
+        Zone::Named(chrono_tz::UTC)
+    }
+
+    fn task(name: &str) -> Task {
+        Task::new(TaskName::new(name).unwrap())
+    }
+
+    #[test]
+    fn parse_should_respect_precedence() {
+        let query = parse("overdue or tag:work and not done");
+
+        assert_eq!(
+            query,
+            Query::Or(
+                Box::new(Query::Overdue),
+                Box::new(Query::And(
+                    Box::new(Query::Tag("work".into())),
+                    Box::new(Query::Not(Box::new(Query::Done)))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn parse_should_combine_adjacent_terms_with_and() {
+        assert_eq!(parse("tag:work done"), parse("tag:work and done"));
+    }
+
+    #[test]
+    fn parse_should_group_with_parentheses() {
+        let query = parse("(overdue or today) and done");
+
+        assert!(matches!(query, Query::And(left, _) if matches!(*left, Query::Or(..))));
+    }
+
+    #[test_case("due:<2022-11-01" => Query::Due(Comparison::Less, "2022-11-01".into()))]
+    #[test_case("due:2022-11-01" => Query::Due(Comparison::Equal, "2022-11-01".into()))]
+    #[test_case("priority:>=medium" => Query::Priority(Comparison::GreaterOrEqual, Priority::Medium))]
+    #[test_case("project:home" => Query::Tag("+home".into()))]
+    #[test_case("name~\"big invoice\"" => Query::Name("big invoice".into()))]
+    fn parse_term(input: &str) -> Query {
+        parse(input)
+    }
+
+    #[test]
+    fn parse_should_accept_relative_dates() {
+        assert!(matches!(
+            parse("due:<tomorrow"),
+            Query::Due(Comparison::Less, _)
+        ));
+        assert!(matches!(
+            parse("due:<=\"end of month\""),
+            Query::Due(Comparison::LessOrEqual, x) if x == "end of month"
+        ));
+    }
+
+    #[test_case("" => 0)]
+    #[test_case("tag:work and" => 12)]
+    #[test_case("due:<someday" => 4)]
+    #[test_case("tag:work or colour:red" => 12)]
+    #[test_case("(done" => 0)]
+    #[test_case("done)" => 4)]
+    #[test_case("name~\"open" => 5 + 5)]
+    #[test_case("priority:urgent" => 9)]
+    fn parse_should_report_column_of_error(input: &str) -> usize {
+        input.parse::<Query>().unwrap_err().position()
+    }
+
+    #[test]
+    fn matches_due_and_tag() {
+        let query = parse("due:<2022-07-01 and tag:work and not done");
+        let work = task("Report +work").with_date(Utc.ymd(2022, 6, 20));
+        let late = task("Report +work").with_date(Utc.ymd(2022, 7, 20));
+        let home = task("Dishes").with_tags(Tag::new("@home"));
+
+        assert!(query.matches(&work, now(), utc()));
+        assert!(!query.matches(&late, now(), utc()));
+        assert!(!query.matches(&home, now(), utc()));
+        assert!(!query.matches(&work.with_completed(Some(now())), now(), utc()));
+    }
+
+    #[test]
+    fn matches_overdue_or_priority() {
+        let query = parse("overdue or priority:high");
+        let overdue = task("Taxes").with_date(Utc.ymd(2022, 6, 1));
+        let high = task("Fire").with_priority(Priority::High);
+        let neither = task("Nap").with_date(Utc.ymd(2022, 6, 30));
+
+        assert!(query.matches(&overdue, now(), utc()));
+        assert!(query.matches(&high, now(), utc()));
+        assert!(!query.matches(&neither, now(), utc()));
+    }
+
+    #[test]
+    fn matches_should_compare_dates_in_timezone() {
+        let zone = Zone::Named(chrono_tz::Asia::Tokyo);
+        let evening = task("Dinner").with_date_time(Utc.ymd(2022, 6, 15).and_hms(20, 0, 0));
+
+        assert!(parse("due:2022-06-16").matches(&evening, now(), zone));
+        assert!(!parse("today").matches(&evening, now(), zone));
+        assert!(parse("due:tomorrow").matches(&evening, now(), zone));
+        assert!(parse("today").matches(&evening, now(), utc()));
+
+        let own_zone = evening.with_timezone(Some(zone));
+        assert!(parse("due:2022-06-16").matches(&own_zone, now(), utc()));
+        assert!(!parse("today").matches(&own_zone, now(), utc()));
+    }
+
+    #[test]
+    fn references_done_should_look_into_subqueries() {
+        assert!(parse("tag:work and not done").references_done());
+        assert!(!parse("tag:work or overdue").references_done());
+    }
+}
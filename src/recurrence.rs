@@ -0,0 +1,221 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use chrono::{prelude::*, Duration};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A rule describing when a task repeats.
+///
+/// The textual form used on the command line and in the edit language is one of
+/// `daily`, `weekly:mon,fri`, `monthly:15` or `every:3` (every third day).
+/// ```
+/// use today::recurrence::Recurrence;
+/// use chrono::Weekday;
+///
+/// let rule = "weekly:mon,fri".parse::<Recurrence>().unwrap();
+///
+/// assert_eq!(rule, Recurrence::Weekly(vec![Weekday::Mon, Weekday::Fri]));
+/// assert_eq!(rule.to_string(), "weekly:mon,fri");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Recurrence {
+    Daily,
+    Weekly(Vec<Weekday>),
+    Monthly(u32),
+    Every(u32),
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum RecurrenceError {
+    #[error("Unknown repeat rule '{0}'")]
+    UnknownRule(String),
+    #[error("Invalid weekday '{0}'")]
+    InvalidWeekday(String),
+    #[error("Expected at least one weekday")]
+    NoWeekdays,
+    #[error("Day of month must be between 1 and 31, got '{0}'")]
+    InvalidDayOfMonth(String),
+    #[error("Number of days must be a positive number, got '{0}'")]
+    InvalidInterval(String),
+}
+
+impl Recurrence {
+    /// Returns true if the rule has an occurrence on `date` regardless of when it started.
+    /// Intervals of days have no anchor and therefore occur on every date.
+    pub fn occurs_on(&self, date: NaiveDate) -> bool {
+        match self {
+            Recurrence::Daily | Recurrence::Every(_) => true,
+            Recurrence::Weekly(days) => days.contains(&date.weekday()),
+            Recurrence::Monthly(day) => date.day() == clamp_day(date.year(), date.month(), *day),
+        }
+    }
+
+    /// Returns the first occurrence on a date after `from`, keeping the wall clock time.
+    /// ```
+    /// use today::recurrence::Recurrence;
+    /// use chrono::prelude::*;
+    ///
+    /// let from = NaiveDate::from_ymd(2022, 1, 31).and_hms(9, 0, 0);
+    /// let next = Recurrence::Monthly(31).next_after(from);
+    ///
+    /// assert_eq!(next, NaiveDate::from_ymd(2022, 2, 28).and_hms(9, 0, 0));
+    /// ```
+    pub fn next_after(&self, from: NaiveDateTime) -> NaiveDateTime {
+        let date = from.date();
+        let next = match self {
+            Recurrence::Daily => date + Duration::days(1),
+            Recurrence::Every(days) => date + Duration::days(i64::from((*days).max(1))),
+            Recurrence::Weekly(_) => (1..=7)
+                .map(|x| date + Duration::days(x))
+                .find(|x| self.occurs_on(*x))
+                .unwrap_or_else(|| date + Duration::weeks(1)),
+            Recurrence::Monthly(day) => {
+                let this_month = clamp_day(date.year(), date.month(), *day);
+                if date.day() < this_month {
+                    date.with_day(this_month).unwrap()
+                } else {
+                    let (year, month) = match date.month() {
+                        12 => (date.year() + 1, 1),
+                        x => (date.year(), x + 1),
+                    };
+                    NaiveDate::from_ymd(year, month, clamp_day(year, month, *day))
+                }
+            }
+        };
+
+        next.and_time(from.time())
+    }
+
+    fn sort_key(&self) -> (u8, u32, Vec<u32>) {
+        match self {
+            Recurrence::Daily => (0, 0, Vec::new()),
+            Recurrence::Weekly(days) => (
+                1,
+                0,
+                days.iter().map(|x| x.num_days_from_monday()).collect(),
+            ),
+            Recurrence::Monthly(day) => (2, *day, Vec::new()),
+            Recurrence::Every(days) => (3, *days, Vec::new()),
+        }
+    }
+}
+
+/// Clamp `day` to the last day of the month.
+fn clamp_day(year: i32, month: u32, day: u32) -> u32 {
+    (28..=day.min(31))
+        .rev()
+        .find(|x| NaiveDate::from_ymd_opt(year, month, *x).is_some())
+        .unwrap_or(day)
+}
+
+impl PartialOrd for Recurrence {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Recurrence {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl std::fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly(days) => {
+                let days = days
+                    .iter()
+                    .map(|x| format!("{x:?}").to_lowercase())
+                    .collect::<Vec<_>>();
+                write!(f, "weekly:{}", days.join(","))
+            }
+            Recurrence::Monthly(day) => write!(f, "monthly:{day}"),
+            Recurrence::Every(days) => write!(f, "every:{days}"),
+        }
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = RecurrenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (rule, argument) = s.split_once(':').unwrap_or((&s, ""));
+
+        match rule {
+            "daily" if argument.is_empty() => Ok(Recurrence::Daily),
+            "weekly" => {
+                let days = argument
+                    .split(',')
+                    .filter(|x| !x.is_empty())
+                    .map(|x| {
+                        x.parse::<Weekday>()
+                            .map_err(|_| RecurrenceError::InvalidWeekday(x.to_owned()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if days.is_empty() {
+                    Err(RecurrenceError::NoWeekdays)
+                } else {
+                    Ok(Recurrence::Weekly(days))
+                }
+            }
+            "monthly" => argument
+                .parse::<u32>()
+                .ok()
+                .filter(|x| (1..=31).contains(x))
+                .map(Recurrence::Monthly)
+                .ok_or_else(|| RecurrenceError::InvalidDayOfMonth(argument.to_owned())),
+            "every" => argument
+                .parse::<u32>()
+                .ok()
+                .filter(|x| *x > 0)
+                .map(Recurrence::Every)
+                .ok_or_else(|| RecurrenceError::InvalidInterval(argument.to_owned())),
+            _ => Err(RecurrenceError::UnknownRule(s.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("daily" => Ok(Recurrence::Daily))]
+    #[test_case("Weekly:Mon,sunday" => Ok(Recurrence::Weekly(vec![Weekday::Mon, Weekday::Sun])))]
+    #[test_case("monthly:15" => Ok(Recurrence::Monthly(15)))]
+    #[test_case("every:3" => Ok(Recurrence::Every(3)))]
+    #[test_case("weekly:" => Err(RecurrenceError::NoWeekdays))]
+    #[test_case("weekly:funday" => Err(RecurrenceError::InvalidWeekday("funday".to_owned())))]
+    #[test_case("monthly:32" => Err(RecurrenceError::InvalidDayOfMonth("32".to_owned())))]
+    #[test_case("every:0" => Err(RecurrenceError::InvalidInterval("0".to_owned())))]
+    #[test_case("hourly" => Err(RecurrenceError::UnknownRule("hourly".to_owned())))]
+    fn parse_rule(input: &str) -> Result<Recurrence, RecurrenceError> {
+        input.parse()
+    }
+
+    #[test_case(Recurrence::Daily, (2022, 12, 31) => (2023, 1, 1))]
+    #[test_case(Recurrence::Every(3), (2022, 2, 27) => (2022, 3, 2))]
+    #[test_case(Recurrence::Weekly(vec![Weekday::Mon, Weekday::Thu]), (2022, 6, 20) => (2022, 6, 23))]
+    #[test_case(Recurrence::Weekly(vec![Weekday::Mon]), (2022, 6, 20) => (2022, 6, 27))]
+    #[test_case(Recurrence::Monthly(15), (2022, 6, 10) => (2022, 6, 15))]
+    #[test_case(Recurrence::Monthly(15), (2022, 12, 15) => (2023, 1, 15))]
+    #[test_case(Recurrence::Monthly(31), (2022, 4, 29) => (2022, 4, 30))]
+    fn next_after(rule: Recurrence, from: (i32, u32, u32)) -> (i32, u32, u32) {
+        let next = rule.next_after(NaiveDate::from_ymd(from.0, from.1, from.2).and_hms(8, 30, 0));
+
+        assert_eq!(next.time(), NaiveTime::from_hms(8, 30, 0));
+        (next.year(), next.month(), next.day())
+    }
+
+    #[test]
+    fn display_should_roundtrip() {
+        for rule in ["daily", "weekly:tue,sat", "monthly:1", "every:14"] {
+            assert_eq!(rule.parse::<Recurrence>().unwrap().to_string(), rule);
+        }
+    }
+}
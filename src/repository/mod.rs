@@ -1,9 +1,493 @@
-use crate::TaskList;
+use std::collections::BTreeMap;
 
+use chrono::prelude::*;
+use thiserror::Error;
+
+use crate::{journal::Operation, Task, TaskId, TaskList};
+
+/// Storage of tasks.
+///
+/// Only [`Repository::all`] and [`Repository::save`] are required, the operations on single
+/// tasks are built on them by default. Backends that can change one task without writing
+/// all of them should override those, and backends that keep removed tasks should override
+/// [`Repository::removed`].
 pub trait Repository {
     type Err;
 
     fn all(&self) -> Result<TaskList, Self::Err>;
 
+    /// Replace all tasks with `tasks`. Backends that keep removed tasks keep the ones that
+    /// are no longer there, with their removal added to their history.
     fn save(&self, tasks: TaskList) -> Result<(), Self::Err>;
+
+    /// The last version of every task that has been removed and not added again. None by
+    /// default, for backends that don't keep removed tasks.
+    fn removed(&self) -> Result<TaskList, Self::Err> {
+        Ok(TaskList::new())
+    }
+
+    /// Get the task with the id `id`.
+    fn get(&self, id: &TaskId) -> Result<Option<Task>, Self::Err> {
+        Ok(self.all()?.iter().find(|x| x.id() == id).cloned())
+    }
+
+    /// Get all tasks matching `filter`.
+    fn query(&self, filter: &Filter) -> Result<TaskList, Self::Err> {
+        let tasks = self
+            .all()?
+            .iter()
+            .filter(|x| filter.matches(x))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Ok(TaskList::from(tasks))
+    }
+
+    /// Add a new task.
+    fn insert(&self, task: Task) -> Result<(), Self::Err> {
+        let mut tasks = self.all()?;
+        tasks.add(task);
+        self.save(tasks)
+    }
+
+    /// Replace the task with the same id as `task`. Returns the replaced task, or `None` if
+    /// there is no task with that id.
+    fn update(&self, task: Task) -> Result<Option<Task>, Self::Err> {
+        let mut tasks = self.all()?;
+        match tasks.edit(task) {
+            Ok(old) => self.save(tasks).map(|_| Some(old)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Remove the task with the id `id`. Returns the removed task with its removal in its
+    /// history, or `None` if there is no task with that id.
+    fn delete(&self, id: &TaskId) -> Result<Option<Task>, Self::Err> {
+        let mut tasks = self.all()?;
+        let old = match tasks.iter().find(|x| x.id() == id) {
+            Some(x) => x.clone(),
+            None => return Ok(None),
+        };
+        tasks.remove(id);
+        self.save(tasks)?;
+
+        let removed = self.removed()?.iter().find(|x| x.id() == id).cloned();
+        Ok(Some(
+            removed.unwrap_or_else(|| old.with_removed_at(Utc::now())),
+        ))
+    }
+
+    /// Make the changes of `operations` one after the other. Operations on tasks that don't
+    /// exist are skipped, like [`Repository::update`] and [`Repository::delete`] do. Backends
+    /// that can write several changes together should override this, so that either all of
+    /// them are stored or none.
+    fn write(&self, operations: &[Operation]) -> Result<(), Self::Err> {
+        for operation in operations {
+            match operation {
+                Operation::Add(task) => self.insert(task.clone())?,
+                Operation::Edit { new, .. } => {
+                    self.update(new.clone())?;
+                }
+                Operation::Remove(task) => {
+                    self.delete(task.id())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Which tasks to get with [`Repository::query`]. A filter without any conditions matches
+/// all tasks.
+/// ```
+/// use today::{repository::Filter, Task, TaskName};
+/// use chrono::prelude::*;
+///
+/// let filter = Filter::new()
+///     .with_completed(false)
+///     .with_due_before(Utc.ymd(2022, 6, 16).and_hms(0, 0, 0))
+///     .with_tag("work");
+/// let task = Task::new(TaskName::new("Report +work").unwrap())
+///     .with_date(Utc.ymd(2022, 6, 15));
+///
+/// assert!(filter.matches(&task));
+/// assert!(!filter.matches(&task.with_date(Utc.ymd(2022, 6, 16))));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    due_before: Option<DateTime<Utc>>,
+    completed: Option<bool>,
+    tag: Option<String>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only tasks due before `until`. Tasks that should be done as soon as possible are
+    /// always due.
+    pub fn with_due_before(self, until: DateTime<Utc>) -> Self {
+        Self {
+            due_before: Some(until),
+            ..self
+        }
+    }
+
+    /// Only tasks that are completed, or only tasks that are not.
+    pub fn with_completed(self, completed: bool) -> Self {
+        Self {
+            completed: Some(completed),
+            ..self
+        }
+    }
+
+    /// Only tasks with a tag matching `pattern`, see [`crate::task::Tag::matches`].
+    pub fn with_tag<T: Into<String>>(self, pattern: T) -> Self {
+        Self {
+            tag: Some(pattern.into()),
+            ..self
+        }
+    }
+
+    pub fn due_before(&self) -> Option<&DateTime<Utc>> {
+        self.due_before.as_ref()
+    }
+
+    pub fn completed(&self) -> Option<bool> {
+        self.completed
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        self.due_before
+            .is_none_or(|until| task.due().is_none_or(|x| *x < until))
+            && self.completed.is_none_or(|x| x == task.is_completed())
+            && self.tag.as_ref().is_none_or(|x| task.has_tag(x))
+    }
+}
+
+/// A task was changed both by us and by someone else since the tasks were read.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("The task '{name}' was changed by another instance at the same time, try again")]
+pub struct Conflict {
+    pub id: TaskId,
+    pub name: String,
+}
+
+/// Merge our changes to `base` with the changes someone else made to it in `theirs`.
+///
+/// Tasks are matched by id. A task changed or removed on only one side keeps that change
+/// and tasks added on either side are kept. A task changed differently on both sides, or
+/// changed on one side and removed on the other, is a [`Conflict`].
+/// ```
+/// use today::{repository::merge, Task, TaskName};
+///
+/// let task = |name| Task::new(TaskName::new(name).unwrap());
+/// let base = vec![task("Shared")];
+/// let ours = vec![base[0].clone(), task("Ours")];
+/// let theirs = vec![base[0].clone(), task("Theirs")];
+///
+/// let merged = merge(&base, &ours, &theirs).unwrap();
+///
+/// let mut names = merged.iter().map(|x| x.name()).collect::<Vec<_>>();
+/// names.sort();
+/// assert_eq!(names, vec!["Ours", "Shared", "Theirs"]);
+/// ```
+pub fn merge(base: &[Task], ours: &[Task], theirs: &[Task]) -> Result<TaskList, Conflict> {
+    let by_id = |tasks: &[Task]| {
+        tasks
+            .iter()
+            .map(|x| (x.id().clone(), x.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let base = by_id(base);
+    let mut ours_by_id = by_id(ours);
+
+    let mut merged = Vec::new();
+    for their in theirs {
+        let id = their.id();
+        let our = ours_by_id.remove(id);
+
+        let task = match (base.get(id), our) {
+            // Added by them
+            (None, None) => Some(their.clone()),
+            // Removed by us
+            (Some(base), None) if base == their => None,
+            (Some(_), None) => return Err(conflict(their)),
+            (Some(base), Some(our)) if base == their => Some(our),
+            (Some(base), Some(our)) if base == &our || our == *their => Some(their.clone()),
+            // Added on both sides with the same id
+            (None, Some(our)) if our == *their => Some(our),
+            (_, Some(_)) => return Err(conflict(their)),
+        };
+        merged.extend(task);
+    }
+
+    // Tasks left are either added by us or removed by them
+    for our in ours.iter().filter(|x| ours_by_id.contains_key(x.id())) {
+        match base.get(our.id()) {
+            None => merged.push(our.clone()),
+            Some(base) if base == our => {}
+            Some(_) => return Err(conflict(our)),
+        }
+    }
+
+    Ok(TaskList::from(merged))
+}
+
+fn conflict(task: &Task) -> Conflict {
+    Conflict {
+        id: task.id().clone(),
+        name: task.name().to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{history::ChangeKind, task::Priority, TaskName};
+    use itertools::Itertools;
+
+    fn task(name: &str) -> Task {
+        Task::new(TaskName::new(name).unwrap())
+    }
+
+    fn names(tasks: TaskList) -> Vec<String> {
+        tasks.iter().map(|x| x.name().to_owned()).sorted().collect()
+    }
+
+    /// A task without its history, to compare tasks regardless of when they were stored.
+    fn stripped(task: Option<Task>) -> Option<Task> {
+        task.map(|x| x.with_history(Vec::new()))
+    }
+
+    /// A repository with only the required methods that keeps removed tasks.
+    #[derive(Default)]
+    struct Memory {
+        tasks: std::cell::RefCell<TaskList>,
+        removed: std::cell::RefCell<Vec<Task>>,
+    }
+
+    impl Repository for Memory {
+        type Err = ();
+
+        fn all(&self) -> Result<TaskList, ()> {
+            Ok(self.tasks.borrow().as_slice().into())
+        }
+
+        fn save(&self, tasks: TaskList) -> Result<(), ()> {
+            let old = self.tasks.replace(tasks);
+            let tasks = self.tasks.borrow();
+            let mut removed = self.removed.borrow_mut();
+            removed.retain(|x| !tasks.iter().any(|y| y.id() == x.id()));
+            removed.extend(
+                old.iter()
+                    .filter(|x| !tasks.iter().any(|y| y.id() == x.id()))
+                    .map(|x| x.clone().with_removed_at(Utc::now())),
+            );
+            Ok(())
+        }
+
+        fn removed(&self) -> Result<TaskList, ()> {
+            Ok(self.removed.borrow().as_slice().into())
+        }
+    }
+
+    #[test]
+    fn default_operations_should_use_all_and_save() {
+        let repo = Memory::default();
+        let first = task("First");
+        let second = task("Second");
+
+        repo.insert(first.clone()).unwrap();
+        repo.insert(second.clone()).unwrap();
+        let renamed = first.clone().with_name(TaskName::new("Renamed").unwrap());
+        assert_eq!(stripped(repo.update(renamed.clone()).unwrap()), Some(first));
+        assert_eq!(
+            stripped(repo.delete(second.id()).unwrap()),
+            Some(second.clone())
+        );
+
+        assert_eq!(stripped(repo.get(renamed.id()).unwrap()), Some(renamed));
+        assert_eq!(repo.get(second.id()), Ok(None));
+        assert_eq!(repo.update(second.clone()), Ok(None));
+        assert_eq!(repo.delete(second.id()), Ok(None));
+    }
+
+    #[test]
+    fn write_should_make_operations_in_order() {
+        let repo = Memory::default();
+        let edited = task("Edited");
+        let removed = task("Removed");
+        repo.insert(edited.clone()).unwrap();
+        repo.insert(removed.clone()).unwrap();
+        let added = task("Added");
+
+        repo.write(&[
+            Operation::Add(added.clone()),
+            Operation::Edit {
+                old: edited.clone(),
+                new: edited.with_name(TaskName::new("Renamed").unwrap()),
+            },
+            Operation::Remove(removed),
+            Operation::Remove(added),
+        ])
+        .unwrap();
+
+        assert_eq!(names(repo.all().unwrap()), vec!["Renamed"]);
+        assert_eq!(names(repo.removed().unwrap()), vec!["Added", "Removed"]);
+    }
+
+    #[test]
+    fn delete_should_return_removed_task_given_backend_without_removed_tasks() {
+        struct Forgetful(std::cell::RefCell<TaskList>);
+
+        impl Repository for Forgetful {
+            type Err = ();
+
+            fn all(&self) -> Result<TaskList, ()> {
+                Ok(self.0.borrow().as_slice().into())
+            }
+
+            fn save(&self, tasks: TaskList) -> Result<(), ()> {
+                self.0.replace(tasks);
+                Ok(())
+            }
+        }
+
+        let repo = Forgetful(Default::default());
+        let task = task("Removed");
+        repo.insert(task.clone()).unwrap();
+
+        let removed = repo.delete(task.id()).unwrap().unwrap();
+
+        assert_eq!(removed.history().last().unwrap().kind, ChangeKind::Removed);
+        assert!(repo.all().unwrap().is_empty());
+        assert!(repo.removed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn delete_should_keep_task_as_removed_until_inserted_again() {
+        let repo = Memory::default();
+        let task = task("Removed");
+        let kinds = |x: &Task| {
+            x.history()
+                .iter()
+                .map(|x| x.kind.clone())
+                .collect::<Vec<_>>()
+        };
+
+        repo.insert(task.clone()).unwrap();
+        let removed = repo.delete(task.id()).unwrap().unwrap();
+        assert_eq!(
+            kinds(&removed),
+            vec![ChangeKind::Created, ChangeKind::Removed]
+        );
+        assert_eq!(
+            repo.removed().unwrap().as_slice(),
+            std::slice::from_ref(&removed)
+        );
+
+        repo.insert(removed).unwrap();
+        let restored = repo.get(task.id()).unwrap().unwrap();
+        assert_eq!(
+            kinds(&restored),
+            vec![
+                ChangeKind::Created,
+                ChangeKind::Removed,
+                ChangeKind::Restored
+            ]
+        );
+        assert!(repo.removed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn query_should_filter_on_all_conditions() {
+        let repo = Memory::default();
+        let now = Utc.ymd(2022, 6, 15).and_hms(12, 0, 0);
+        for task in [
+            task("Now +work"),
+            task("Later +work").with_date(Utc.ymd(2022, 6, 20)),
+            task("Done +work").with_completed(Some(now)),
+            task("Home"),
+        ] {
+            repo.insert(task).unwrap();
+        }
+
+        let filter = Filter::new()
+            .with_due_before(now)
+            .with_completed(false)
+            .with_tag("+work");
+
+        assert_eq!(names(repo.query(&filter).unwrap()), vec!["Now +work"]);
+        assert_eq!(repo.query(&Filter::new()).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn merge_should_keep_changes_from_both_sides() {
+        let base = vec![
+            task("Edited by us"),
+            task("Edited by them"),
+            task("Removed"),
+        ];
+        let ours = vec![
+            base[0].clone().with_priority(Priority::High),
+            base[1].clone(),
+            base[2].clone(),
+        ];
+        let theirs = vec![
+            base[0].clone(),
+            base[1].clone().with_priority(Priority::Low),
+        ];
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+
+        let priorities = merged
+            .iter()
+            .map(|x| (x.name(), x.priority()))
+            .sorted()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            priorities,
+            vec![
+                ("Edited by them", Priority::Low),
+                ("Edited by us", Priority::High)
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_should_remove_tasks_we_removed() {
+        let base = vec![task("Keep"), task("Remove")];
+        let ours = vec![base[0].clone()];
+        let theirs = vec![base[0].clone(), base[1].clone(), task("New")];
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+
+        assert_eq!(names(merged), vec!["Keep", "New"]);
+    }
+
+    #[test]
+    fn merge_should_fail_given_changes_on_both_sides() {
+        let base = vec![task("Contested")];
+        let ours = vec![base[0].clone().with_priority(Priority::High)];
+        let theirs = vec![base[0].clone().with_priority(Priority::Low)];
+
+        let result = merge(&base, &ours, &theirs);
+
+        assert_eq!(result.unwrap_err().name, "Contested");
+    }
+
+    #[test]
+    fn merge_should_fail_given_change_to_task_removed_by_them() {
+        let base = vec![task("Contested")];
+        let ours = vec![base[0].clone().with_priority(Priority::High)];
+
+        assert!(merge(&base, &ours, &[]).is_err());
+    }
 }
@@ -110,6 +110,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::let_and_return)]
     fn combine_macro() {
         let x = crate::combine! {
             Last::from(53) => None, 42, {let b = None; b},
@@ -0,0 +1,567 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{Error, Result},
+    path::Path,
+    time::Duration,
+};
+
+use chrono::prelude::*;
+use rusqlite::{
+    params, params_from_iter, Connection, OptionalExtension, Transaction, TransactionBehavior,
+};
+
+use crate::{
+    journal::Operation,
+    repository::{self, Filter},
+    task::Tag,
+    Task, TaskId, TaskList,
+};
+
+/// Every migration brings the schema up one version. The version of a database is kept in
+/// `PRAGMA user_version`, new migrations are only ever appended to this list.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE tasks (
+        id        TEXT PRIMARY KEY,
+        name      TEXT NOT NULL,
+        due       TEXT,
+        completed TEXT,
+        priority  INTEGER NOT NULL DEFAULT 0,
+        data      TEXT NOT NULL
+    );
+    CREATE INDEX tasks_due ON tasks (due);
+    CREATE INDEX tasks_completed ON tasks (completed);
+
+    CREATE TABLE task_tags (
+        task_id TEXT NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+        tag     TEXT NOT NULL,
+        PRIMARY KEY (task_id, tag)
+    );
+    CREATE INDEX task_tags_tag ON task_tags (tag);
+",
+    "
+    CREATE TABLE removed_tasks (
+        id   TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+",
+];
+
+/// Tasks stored in an SQLite database.
+///
+/// The whole task is kept as JSON in the `data` column, while the columns next to it hold
+/// copies of the fields that are searched on so they can be indexed. Like
+/// [`crate::json::JsonRepository`] a save merges with changes other instances made since
+/// the tasks were read. Removed tasks are kept in their own table.
+pub struct SqliteRepository {
+    connection: Connection,
+    read: RefCell<Option<Vec<Task>>>,
+}
+
+impl SqliteRepository {
+    /// Open the database at `path`, creating it and migrating its schema if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        if let Some(directory) = path.as_ref().parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        Self::new(Connection::open(path).map_err(Error::other)?)
+    }
+
+    /// A database that only lives as long as the repository.
+    pub fn in_memory() -> Result<Self> {
+        Self::new(Connection::open_in_memory().map_err(Error::other)?)
+    }
+
+    fn new(mut connection: Connection) -> Result<Self> {
+        connection
+            .busy_timeout(Duration::from_secs(5))
+            .and_then(|_| connection.pragma_update(None, "foreign_keys", true))
+            .and_then(|_| migrate(&mut connection))
+            .map_err(Error::other)?;
+
+        Ok(Self {
+            connection,
+            read: RefCell::new(None),
+        })
+    }
+
+    /// The version of the schema, which is the number of migrations applied.
+    pub fn version(&self) -> Result<usize> {
+        schema_version(&self.connection).map_err(Error::other)
+    }
+
+    /// Add `tasks` to the ones already stored, replacing tasks with the same id.
+    pub fn import(&self, tasks: TaskList) -> Result<()> {
+        let transaction = self.transaction()?;
+        for task in tasks.iter() {
+            upsert(&transaction, task)?;
+        }
+
+        transaction.commit().map_err(Error::other)
+    }
+
+    fn transaction(&self) -> Result<Transaction<'_>> {
+        Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)
+            .map_err(Error::other)
+    }
+}
+
+impl repository::Repository for SqliteRepository {
+    type Err = std::io::Error;
+
+    fn all(&self) -> Result<TaskList> {
+        let tasks = read_all(&self.connection)?;
+
+        self.read.replace(Some(tasks.clone()));
+        Ok(TaskList::from(tasks))
+    }
+
+    fn get(&self, id: &TaskId) -> Result<Option<Task>> {
+        get(&self.connection, id)
+    }
+
+    /// Get the tasks matching `filter`, using the indexes on the due date and tags.
+    fn query(&self, filter: &Filter) -> Result<TaskList> {
+        let mut conditions = vec!["1"];
+        let mut values = Vec::new();
+
+        if let Some(until) = filter.due_before() {
+            conditions.push("(due IS NULL OR due < ?)");
+            values.push(timestamp(until));
+        }
+        match filter.completed() {
+            Some(true) => conditions.push("completed IS NOT NULL"),
+            Some(false) => conditions.push("completed IS NULL"),
+            None => {}
+        }
+        if let Some(tag) = filter.tag() {
+            conditions.push("id IN (SELECT task_id FROM task_tags WHERE tag = ? OR tag = ?)");
+            match Tag::new(tag) {
+                Some(tag) => values.extend([tag.to_string(), tag.to_string()]),
+                None => values.extend([format!("+{tag}"), format!("@{tag}")]),
+            }
+        }
+
+        let sql = format!(
+            "SELECT data FROM tasks WHERE {} ORDER BY id",
+            conditions.join(" AND ")
+        );
+        let tasks = query_tasks(&self.connection, &sql, params_from_iter(values))?;
+        Ok(TaskList::from(tasks))
+    }
+
+    fn insert(&self, task: Task) -> Result<()> {
+        let transaction = self.transaction()?;
+        upsert(&transaction, &task.with_created_at(Utc::now()))?;
+        transaction.commit().map_err(Error::other)
+    }
+
+    fn update(&self, task: Task) -> Result<Option<Task>> {
+        let transaction = self.transaction()?;
+        let old = get(&transaction, task.id())?;
+        if let Some(old) = &old {
+            upsert(&transaction, &task.with_history_of(old, Utc::now()))?;
+        }
+
+        transaction.commit().map_err(Error::other)?;
+        Ok(old)
+    }
+
+    fn delete(&self, id: &TaskId) -> Result<Option<Task>> {
+        let transaction = self.transaction()?;
+        let removed = get(&transaction, id)?.map(|x| x.with_removed_at(Utc::now()));
+        if let Some(task) = &removed {
+            remove(&transaction, task)?;
+        }
+
+        transaction.commit().map_err(Error::other)?;
+        Ok(removed)
+    }
+
+    /// Make the changes of `operations` in a single transaction.
+    fn write(&self, operations: &[Operation]) -> Result<()> {
+        let transaction = self.transaction()?;
+        let now = Utc::now();
+        for operation in operations {
+            match operation {
+                Operation::Add(task) => upsert(&transaction, &task.clone().with_created_at(now))?,
+                Operation::Edit { new, .. } => {
+                    if let Some(old) = get(&transaction, new.id())? {
+                        upsert(&transaction, &new.clone().with_history_of(&old, now))?;
+                    }
+                }
+                Operation::Remove(task) => {
+                    if let Some(old) = get(&transaction, task.id())? {
+                        remove(&transaction, &old.with_removed_at(now))?;
+                    }
+                }
+            }
+        }
+
+        transaction.commit().map_err(Error::other)
+    }
+
+    fn save(&self, tasks: TaskList) -> Result<()> {
+        let transaction = self.transaction()?;
+        let current = read_all(&transaction)?;
+
+        let tasks = match self.read.borrow().as_deref() {
+            Some(read) if read != current => {
+                repository::merge(read, tasks.as_slice(), &current).map_err(Error::other)?
+            }
+            _ => tasks,
+        };
+
+        let current = current
+            .into_iter()
+            .map(|x| (x.id().clone(), x))
+            .collect::<BTreeMap<_, _>>();
+        for task in tasks.iter() {
+            if current.get(task.id()) != Some(task) {
+                upsert(&transaction, task)?;
+            }
+        }
+        let kept = tasks.iter().map(Task::id).collect::<BTreeSet<_>>();
+        let now = Utc::now();
+        for (id, task) in current {
+            if !kept.contains(&id) {
+                remove(&transaction, &task.with_removed_at(now))?;
+            }
+        }
+
+        transaction.commit().map_err(Error::other)?;
+        self.read.replace(Some(read_all(&self.connection)?));
+        Ok(())
+    }
+
+    fn removed(&self) -> Result<TaskList> {
+        let sql = "SELECT data FROM removed_tasks ORDER BY id";
+        Ok(TaskList::from(query_tasks(&self.connection, sql, [])?))
+    }
+}
+
+fn schema_version(connection: &Connection) -> rusqlite::Result<usize> {
+    connection.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Apply the migrations the database has not seen yet.
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Exclusive)?;
+    let version = schema_version(&transaction)?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+    }
+
+    transaction.commit()
+}
+
+fn read_all(connection: &Connection) -> Result<Vec<Task>> {
+    query_tasks(connection, "SELECT data FROM tasks ORDER BY id", [])
+}
+
+fn get(connection: &Connection, id: &TaskId) -> Result<Option<Task>> {
+    connection
+        .query_row(
+            "SELECT data FROM tasks WHERE id = ?1",
+            [id.to_string()],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map_err(Error::other)?
+        .map(|x| Ok(serde_json::from_str(&x)?))
+        .transpose()
+}
+
+/// Run `sql`, which selects the `data` column, and read the tasks from it.
+fn query_tasks<P: rusqlite::Params>(
+    connection: &Connection,
+    sql: &str,
+    params: P,
+) -> Result<Vec<Task>> {
+    let mut statement = connection.prepare(sql).map_err(Error::other)?;
+
+    let tasks = statement
+        .query_map(params, |row| row.get::<_, String>(0))
+        .map_err(Error::other)?
+        .map(|x| Ok(serde_json::from_str::<Task>(&x.map_err(Error::other)?)?))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(tasks)
+}
+
+/// Insert or replace a task together with its tags.
+fn upsert(connection: &Connection, task: &Task) -> Result<()> {
+    let id = task.id().to_string();
+    let data = serde_json::to_string(task)?;
+
+    connection
+        .execute(
+            "INSERT INTO tasks (id, name, due, completed, priority, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                due = excluded.due,
+                completed = excluded.completed,
+                priority = excluded.priority,
+                data = excluded.data",
+            params![
+                id,
+                task.name(),
+                task.due().map(timestamp),
+                task.completed().map(timestamp),
+                task.priority() as i64,
+                data
+            ],
+        )
+        .and_then(|_| connection.execute("DELETE FROM task_tags WHERE task_id = ?1", [&id]))
+        .and_then(|_| connection.execute("DELETE FROM removed_tasks WHERE id = ?1", [&id]))
+        .map_err(Error::other)?;
+
+    for tag in task.tags() {
+        connection
+            .execute(
+                "INSERT INTO task_tags (task_id, tag) VALUES (?1, ?2)",
+                params![id, tag.as_str()],
+            )
+            .map_err(Error::other)?;
+    }
+
+    Ok(())
+}
+
+/// Move `task` from the tasks to the removed tasks.
+fn remove(connection: &Connection, task: &Task) -> Result<()> {
+    let id = task.id().to_string();
+    let data = serde_json::to_string(task)?;
+
+    connection
+        .execute("DELETE FROM tasks WHERE id = ?1", [&id])
+        .and_then(|_| {
+            connection.execute(
+                "INSERT OR REPLACE INTO removed_tasks (id, data) VALUES (?1, ?2)",
+                params![id, data],
+            )
+        })
+        .map_err(Error::other)?;
+
+    Ok(())
+}
+
+/// A timestamp where the text order is the same as the time order.
+fn timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{history::ChangeKind, repository::Repository, task::Tag, TaskName};
+
+    fn task(name: &str) -> Task {
+        Task::new(TaskName::new(name).unwrap())
+    }
+
+    /// A task without its history, to compare tasks regardless of when they were stored.
+    fn stripped(task: Option<Task>) -> Option<Task> {
+        task.map(|x| x.with_history(Vec::new()))
+    }
+
+    fn names(tasks: TaskList) -> Vec<String> {
+        let mut names = tasks
+            .iter()
+            .map(|x| x.name().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn open_should_migrate_to_latest_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("today").join("tasks.db");
+
+        SqliteRepository::open(&path).unwrap();
+        assert_eq!(
+            SqliteRepository::open(&path).unwrap().version().unwrap(),
+            MIGRATIONS.len()
+        );
+    }
+
+    #[test]
+    fn save_should_store_whole_task() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let saved = task("Dentist +health")
+            .with_date_time(Utc.ymd(2022, 6, 15).and_hms(9, 0, 0))
+            .with_tags(Tag::new("@town"));
+
+        repo.save(TaskList::from(vec![saved.clone(), task("Nap")]))
+            .unwrap();
+        let mut tasks = repo.all().unwrap();
+        tasks.remove(saved.id());
+        repo.save(tasks).unwrap();
+        repo.save(TaskList::from(vec![saved.clone()])).unwrap();
+
+        assert_eq!(repo.all().unwrap().as_slice(), &[saved]);
+    }
+
+    #[test]
+    fn save_should_keep_tags_searchable() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        repo.save(TaskList::from(vec![task("Report +work"), task("Nap")]))
+            .unwrap();
+
+        let count: i64 = repo
+            .connection
+            .query_row(
+                "SELECT COUNT(*) FROM task_tags WHERE tag = '+work'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn query_should_skip_completed_and_later_tasks() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let now = Utc.ymd(2022, 6, 15).and_hms(12, 0, 0);
+        repo.import(TaskList::from(vec![
+            task("Now"),
+            task("Morning").with_date_time(Utc.ymd(2022, 6, 15).and_hms(8, 0, 0)),
+            task("Tomorrow").with_date_time(Utc.ymd(2022, 6, 16).and_hms(8, 0, 0)),
+            task("Done")
+                .with_date_time(Utc.ymd(2022, 6, 15).and_hms(8, 0, 0))
+                .with_completed(Some(now)),
+        ]))
+        .unwrap();
+
+        let filter = Filter::new().with_due_before(now).with_completed(false);
+        assert_eq!(names(repo.query(&filter).unwrap()), vec!["Morning", "Now"]);
+    }
+
+    #[test]
+    fn query_should_match_tags_with_and_without_prefix() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        repo.import(TaskList::from(vec![
+            task("Report +work"),
+            task("Call @work"),
+            task("Nap"),
+        ]))
+        .unwrap();
+
+        let query = |tag| names(repo.query(&Filter::new().with_tag(tag)).unwrap());
+        assert_eq!(query("work"), vec!["Call @work", "Report +work"]);
+        assert_eq!(query("+work"), vec!["Report +work"]);
+    }
+
+    #[test]
+    fn single_task_operations_should_change_only_that_task() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let first = task("First +old");
+        let second = task("Second");
+        repo.insert(first.clone()).unwrap();
+        repo.insert(second.clone()).unwrap();
+
+        let renamed = first
+            .clone()
+            .with_name(TaskName::new("Renamed +new").unwrap());
+        assert_eq!(stripped(repo.update(renamed.clone()).unwrap()), Some(first));
+        assert_eq!(
+            stripped(repo.delete(second.id()).unwrap()),
+            Some(second.clone())
+        );
+        assert_eq!(repo.update(second.clone()).unwrap(), None);
+
+        assert_eq!(stripped(repo.get(renamed.id()).unwrap()), Some(renamed));
+        assert_eq!(repo.get(second.id()).unwrap(), None);
+        assert!(repo
+            .query(&Filter::new().with_tag("old"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn write_should_make_all_operations() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let edited = task("Edited +old");
+        let removed = task("Removed");
+        repo.import(TaskList::from(vec![edited.clone(), removed.clone()]))
+            .unwrap();
+
+        let renamed = edited.clone().with_name(TaskName::new("Renamed").unwrap());
+        repo.write(&[
+            Operation::Add(task("Added")),
+            Operation::Edit {
+                old: edited,
+                new: renamed.clone(),
+            },
+            Operation::Remove(removed),
+        ])
+        .unwrap();
+
+        assert_eq!(names(repo.all().unwrap()), vec!["Added", "Renamed"]);
+        assert_eq!(names(repo.removed().unwrap()), vec!["Removed"]);
+        assert!(repo
+            .query(&Filter::new().with_tag("old"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn delete_should_keep_task_as_removed_until_inserted_again() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let task = task("Removed");
+        repo.insert(task.clone()).unwrap();
+
+        let removed = repo.delete(task.id()).unwrap().unwrap();
+        assert_eq!(
+            repo.removed().unwrap().as_slice(),
+            std::slice::from_ref(&removed)
+        );
+        assert_eq!(removed.history().last().unwrap().kind, ChangeKind::Removed);
+
+        repo.insert(removed).unwrap();
+        let restored = repo.get(task.id()).unwrap().unwrap();
+        assert_eq!(
+            restored.history().last().unwrap().kind,
+            ChangeKind::Restored
+        );
+        assert!(repo.removed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn save_should_keep_tasks_it_no_longer_has_as_removed() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        repo.import(TaskList::from(vec![task("Kept"), task("Removed")]))
+            .unwrap();
+
+        let all = repo.all().unwrap();
+        let kept = all.iter().filter(|x| x.name() == "Kept").cloned();
+        repo.save(TaskList::from(kept.collect::<Vec<_>>())).unwrap();
+
+        assert_eq!(names(repo.removed().unwrap()), vec!["Removed"]);
+    }
+
+    #[test]
+    fn save_should_merge_with_changes_from_another_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tasks.db");
+        let first = SqliteRepository::open(&path).unwrap();
+        let second = SqliteRepository::open(&path).unwrap();
+
+        let mut first_tasks = first.all().unwrap();
+        let mut second_tasks = second.all().unwrap();
+        first_tasks.add(task("First"));
+        second_tasks.add(task("Second"));
+        first.save(first_tasks).unwrap();
+        second.save(second_tasks).unwrap();
+
+        assert_eq!(names(first.all().unwrap()), vec!["First", "Second"]);
+    }
+}
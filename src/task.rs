@@ -1,11 +1,18 @@
-use std::ops::Deref;
+use std::{collections::BTreeSet, ops::Deref};
 
-use chrono::prelude::*;
+use chrono::{prelude::*, Duration};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use uuid::Uuid;
+
+use crate::{
+    history::{self, Change, ChangeKind},
+    recurrence::Recurrence,
+    timezone::Zone,
+    tracking::{Minutes, TimeEntry, TrackingError},
+};
 /// `TaskName` is a any non empty string with at least one printable character with surrounding
 /// whitespaces trimmed. `TaskName` is compared case insensitive.
 /// ```
@@ -43,6 +50,21 @@ impl TaskName {
     pub fn into_inner(self) -> String {
         self.0
     }
+
+    /// All the words in the name that are tags.
+    /// ```
+    /// use today::task::{Tag, TaskName};
+    ///
+    /// let name = TaskName::new("Call Dave +wedding @phone").unwrap();
+    ///
+    /// assert_eq!(
+    ///     name.tags().collect::<Vec<_>>(),
+    ///     vec![Tag::new("+wedding").unwrap(), Tag::new("@phone").unwrap()]
+    /// );
+    /// ```
+    pub fn tags(&self) -> impl Iterator<Item = Tag> + '_ {
+        self.0.split_whitespace().filter_map(Tag::new)
+    }
 }
 
 impl PartialEq<&str> for TaskName {
@@ -63,6 +85,71 @@ impl AsRef<String> for TaskName {
     }
 }
 
+/// A tag is a word prefixed with `+` for a project or `@` for a context.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct Tag(String);
+
+impl Tag {
+    pub fn new(value: &str) -> Option<Self> {
+        let mut chars = value.chars();
+        let is_tag = matches!(chars.next(), Some('+' | '@'))
+            && chars.next().is_some()
+            && !value.contains(char::is_whitespace);
+
+        is_tag.then(|| Self(value.to_owned()))
+    }
+
+    /// The tag without its prefix.
+    pub fn name(&self) -> &str {
+        &self.0[1..]
+    }
+
+    pub fn is_project(&self) -> bool {
+        self.0.starts_with('+')
+    }
+
+    pub fn is_context(&self) -> bool {
+        self.0.starts_with('@')
+    }
+
+    /// Returns true if `pattern` is this tag or, if `pattern` has no prefix, the name of this tag.
+    /// ```
+    /// use today::task::Tag;
+    ///
+    /// let tag = Tag::new("+work").unwrap();
+    ///
+    /// assert!(tag.matches("work"));
+    /// assert!(tag.matches("+work"));
+    /// assert!(!tag.matches("@work"));
+    /// ```
+    pub fn matches(&self, pattern: &str) -> bool {
+        if pattern.starts_with(['+', '@']) {
+            self.0 == pattern
+        } else {
+            self.name() == pattern
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::str::FromStr for Tag {
+    type Err = TaskError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Tag::new(s).ok_or_else(|| TaskError::InvalidTag(s.to_owned()))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(transparent)]
 pub struct TaskId(Uuid);
@@ -91,12 +178,104 @@ impl Default for TaskId {
     }
 }
 
+/// How important a task is. Tasks with a higher priority are listed first.
+/// ```
+/// use today::task::Priority;
+///
+/// assert_eq!("High".parse::<Priority>(), Ok(Priority::High));
+/// assert!(Priority::High > Priority::Low);
+/// ```
+#[derive(
+    Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Priority::None => write!(f, "none"),
+            Priority::Low => write!(f, "low"),
+            Priority::Medium => write!(f, "medium"),
+            Priority::High => write!(f, "high"),
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = TaskError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Priority::None),
+            "low" => Ok(Priority::Low),
+            "medium" => Ok(Priority::Medium),
+            "high" => Ok(Priority::High),
+            _ => Err(TaskError::InvalidPriority(s.to_owned())),
+        }
+    }
+}
+
+/// A step of a task that is ticked off on its own.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChecklistItem {
+    name: TaskName,
+    #[serde(default)]
+    done: bool,
+}
+
+impl ChecklistItem {
+    pub fn new(name: TaskName) -> Self {
+        Self { name, done: false }
+    }
+
+    pub fn with_done(self, done: bool) -> Self {
+        Self { done, ..self }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Task {
     #[serde(default)]
     id: TaskId,
     name: TaskName,
     due: Option<DateTime<Utc>>,
+    #[serde(default)]
+    completed: Option<DateTime<Utc>>,
+    #[serde(default)]
+    repeat: Option<Recurrence>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    tags: BTreeSet<Tag>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<Zone>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    checklist: Vec<ChecklistItem>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    depends_on: BTreeSet<TaskId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimate: Option<Minutes>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    time_entries: Vec<TimeEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<Change>,
 }
 
 impl Task {
@@ -113,6 +292,17 @@ impl Task {
             id: Default::default(),
             name,
             due: None,
+            completed: None,
+            repeat: None,
+            priority: Priority::None,
+            tags: BTreeSet::new(),
+            timezone: None,
+            notes: None,
+            checklist: Vec::new(),
+            depends_on: BTreeSet::new(),
+            estimate: None,
+            time_entries: Vec::new(),
+            history: Vec::new(),
         }
     }
 
@@ -157,6 +347,66 @@ impl Task {
         self
     }
 
+    /// Set when the task was completed. `None` marks the task as not done.
+    /// ```
+    /// use today::{Task, TaskName};
+    /// use chrono::prelude::*;
+    ///
+    /// let task = Task::new(TaskName::new("Meet Dave").unwrap())
+    ///     .with_completed(Some(Utc.ymd(2020, 2, 23).and_hms(16, 0, 0)));
+    ///
+    /// assert!(task.is_completed());
+    /// ```
+    pub fn with_completed(mut self, completed: Option<DateTime<Utc>>) -> Self {
+        self.completed = completed;
+        self
+    }
+
+    /// Set the rule for when the task repeats. `None` makes it a one time task.
+    pub fn with_repeat(mut self, repeat: Option<Recurrence>) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Add tags to the task besides the ones written in its name.
+    pub fn with_tags<I: IntoIterator<Item = Tag>>(mut self, tags: I) -> Self {
+        self.tags.extend(tags);
+        self
+    }
+
+    /// Remove the tags that match any of `patterns`, see [`Tag::matches`]. Tags written in the
+    /// name are removed from it too, unless the name would be left empty.
+    pub fn without_tags<S: AsRef<str>>(mut self, patterns: &[S]) -> Self {
+        let is_removed = |tag: &Tag| patterns.iter().any(|x| tag.matches(x.as_ref()));
+        self.tags.retain(|x| !is_removed(x));
+
+        if self.name.tags().any(|x| is_removed(&x)) {
+            let name = self
+                .name
+                .as_str()
+                .split_whitespace()
+                .filter(|x| !Tag::new(x).is_some_and(|x| is_removed(&x)))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if let Some(name) = TaskName::new(&name) {
+                self.name = name;
+            }
+        }
+        self
+    }
+
+    /// Set the time zone the task belongs to. `None` makes the task follow the time
+    /// zone of the user.
+    pub fn with_timezone(mut self, timezone: Option<Zone>) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
     /// Add a time to the task if date has been set first.
     /// If `due` is None then this has no effect
     pub fn and_time(mut self, time: NaiveTime) -> Self {
@@ -180,22 +430,329 @@ impl Task {
     pub fn id(&self) -> &TaskId {
         &self.id
     }
+
+    /// Get the date and time the task was completed.
+    pub fn completed(&self) -> Option<&DateTime<Utc>> {
+        self.completed.as_ref()
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.completed.is_some()
+    }
+
+    /// Get the priority of the task.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Get all tags of the task, both those written in its name and those added separately.
+    /// ```
+    /// use today::{task::Tag, Task, TaskName};
+    ///
+    /// let task = Task::new(TaskName::new("Buy milk @store").unwrap())
+    ///     .with_tags(Tag::new("+groceries"));
+    ///
+    /// assert_eq!(task.tags().len(), 2);
+    /// ```
+    pub fn tags(&self) -> BTreeSet<Tag> {
+        self.name.tags().chain(self.tags.iter().cloned()).collect()
+    }
+
+    /// The tags given to the task, without the ones in its name.
+    pub(crate) fn explicit_tags(&self) -> &BTreeSet<Tag> {
+        &self.tags
+    }
+
+    /// Returns true if any of the tags matches `pattern`, see [`Tag::matches`].
+    pub fn has_tag(&self, pattern: &str) -> bool {
+        self.tags().iter().any(|x| x.matches(pattern))
+    }
+
+    /// Get the rule for when the task repeats.
+    pub fn repeat(&self) -> Option<&Recurrence> {
+        self.repeat.as_ref()
+    }
+
+    /// Get the time zone of the task if it has one of its own.
+    pub fn timezone(&self) -> Option<Zone> {
+        self.timezone
+    }
+
+    /// The time zone the dates of the task are in, its own or else `zone` of the user.
+    pub fn zone_or(&self, zone: Zone) -> Zone {
+        self.timezone.unwrap_or(zone)
+    }
+
+    /// A longer description of the task, it may span many lines.
+    pub fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    /// Set the notes of the task. Notes with only whitespace are removed.
+    /// ```
+    /// use today::{Task, TaskName};
+    ///
+    /// let task = Task::new(TaskName::new("Plan trip").unwrap())
+    ///     .with_notes(Some("Book hotel\nRent a car\n".to_owned()));
+    ///
+    /// assert_eq!(task.notes(), Some("Book hotel\nRent a car"));
+    /// assert_eq!(task.with_notes(Some(" \n".to_owned())).notes(), None);
+    /// ```
+    pub fn with_notes(mut self, notes: Option<String>) -> Self {
+        self.notes = notes
+            .map(|x| x.trim_end().to_owned())
+            .filter(|x| !x.trim().is_empty());
+        self
+    }
+
+    /// The steps of the task, in the order they were added.
+    pub fn checklist(&self) -> &[ChecklistItem] {
+        &self.checklist
+    }
+
+    /// Add `item` last in the checklist.
+    pub fn with_item(mut self, item: ChecklistItem) -> Self {
+        self.checklist.push(item);
+        self
+    }
+
+    /// Tick off the checklist item at `index`, counted from zero.
+    /// ```
+    /// use today::{task::ChecklistItem, Task, TaskName};
+    ///
+    /// let item = |x| ChecklistItem::new(TaskName::new(x).unwrap());
+    /// let task = Task::new(TaskName::new("Release v2").unwrap())
+    ///     .with_item(item("Tag"))
+    ///     .with_item(item("Build"))
+    ///     .with_item(item("Announce"));
+    ///
+    /// let task = task.with_ticked(1).unwrap();
+    ///
+    /// assert_eq!(task.progress(), Some((1, 3)));
+    /// assert!(task.with_ticked(3).is_err());
+    /// ```
+    pub fn with_ticked(mut self, index: usize) -> Result<Self, TaskError> {
+        let item = self
+            .checklist
+            .get_mut(index)
+            .ok_or(TaskError::InvalidItem(index + 1))?;
+        item.done = true;
+        Ok(self)
+    }
+
+    /// How many of the checklist items are done and how many there are, if there are any.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        let done = self.checklist.iter().filter(|x| x.is_done()).count();
+        (!self.checklist.is_empty()).then_some((done, self.checklist.len()))
+    }
+
+    /// The tasks that have to be done before this one can start.
+    pub fn depends_on(&self) -> &BTreeSet<TaskId> {
+        &self.depends_on
+    }
+
+    /// Make this task wait for the task with the id `id`. Use [`TaskList::add_dependency`]
+    /// to make sure the tasks don't end up waiting for each other.
+    pub fn with_dependency(mut self, id: TaskId) -> Self {
+        self.depends_on.insert(id);
+        self
+    }
+
+    pub fn without_dependency(mut self, id: &TaskId) -> Self {
+        self.depends_on.remove(id);
+        self
+    }
+
+    /// Returns true if any of the tasks in `tasks` that this task depends on is not
+    /// completed yet.
+    pub fn is_blocked(&self, tasks: &[Task]) -> bool {
+        tasks
+            .iter()
+            .any(|x| !x.is_completed() && self.depends_on.contains(x.id()))
+    }
+
+    /// How long the task is expected to take.
+    pub fn estimate(&self) -> Option<Minutes> {
+        self.estimate
+    }
+
+    pub fn with_estimate(mut self, estimate: Option<Minutes>) -> Self {
+        self.estimate = estimate;
+        self
+    }
+
+    /// The periods of time spent on the task, oldest first.
+    pub fn time_entries(&self) -> &[TimeEntry] {
+        &self.time_entries
+    }
+
+    /// Returns true if time is being tracked for the task.
+    pub fn is_running(&self) -> bool {
+        self.time_entries.iter().any(|x| x.is_running())
+    }
+
+    /// Start tracking time for the task at `time`.
+    pub fn with_started(mut self, time: DateTime<Utc>) -> Result<Self, TrackingError> {
+        if self.is_running() {
+            return Err(TrackingError::AlreadyRunning(self.name().to_owned()));
+        }
+        self.time_entries.push(TimeEntry {
+            start: time,
+            end: None,
+        });
+        Ok(self)
+    }
+
+    /// Stop tracking time for the task at `time`.
+    pub fn with_stopped(mut self, time: DateTime<Utc>) -> Result<Self, TrackingError> {
+        match self.time_entries.iter_mut().find(|x| x.is_running()) {
+            Some(entry) => {
+                entry.end = Some(time.max(entry.start));
+                Ok(self)
+            }
+            None => Err(TrackingError::NotRunning(self.name().to_owned())),
+        }
+    }
+
+    /// The time spent on the task between `from` and `to`, time still being tracked
+    /// counts until `now`.
+    /// ```
+    /// use today::{Task, TaskName};
+    /// use chrono::prelude::*;
+    ///
+    /// let at = |hour| Utc.ymd(2022, 6, 15).and_hms(hour, 0, 0);
+    /// let task = Task::new(TaskName::new("Write report").unwrap())
+    ///     .with_started(at(9)).unwrap()
+    ///     .with_stopped(at(11)).unwrap()
+    ///     .with_started(at(13)).unwrap();
+    ///
+    /// assert_eq!(task.tracked(at(0), at(23), at(14)), chrono::Duration::hours(3));
+    /// assert_eq!(task.tracked(at(10), at(23), at(13)), chrono::Duration::hours(1));
+    /// ```
+    pub fn tracked(&self, from: DateTime<Utc>, to: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+        self.time_entries
+            .iter()
+            .map(|x| x.duration_between(from, to, now))
+            .fold(Duration::zero(), |sum, x| sum + x)
+    }
+
+    /// All the time spent on the task, time still being tracked counts until `now`.
+    pub fn time_spent(&self, now: DateTime<Utc>) -> Duration {
+        self.time_entries
+            .first()
+            .map_or_else(Duration::zero, |x| self.tracked(x.start, now, now))
+    }
+
+    /// How much of the estimate is left given all the time spent on the task until `now`,
+    /// if it has an estimate.
+    pub fn remaining(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.estimate
+            .map(|x| (x.to_duration() - self.time_spent(now)).max(Duration::zero()))
+    }
+
+    /// Everything that happened to the task, oldest first.
+    pub fn history(&self) -> &[Change] {
+        &self.history
+    }
+
+    pub fn with_history(mut self, history: Vec<Change>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Continue the history of `old`, which this task replaces, with what changed
+    /// between them at `time`.
+    pub fn with_history_of(mut self, old: &Task, time: DateTime<Utc>) -> Self {
+        let changes = history::changes(old, &self)
+            .into_iter()
+            .map(|kind| Change { time, kind });
+        self.history = old.history.iter().cloned().chain(changes).collect();
+        self
+    }
+
+    /// Record that the task was created at `time`, unless it already has a history. A task
+    /// that was removed last is recorded as restored instead.
+    pub fn with_created_at(mut self, time: DateTime<Utc>) -> Self {
+        let kind = match self.history.last() {
+            None => ChangeKind::Created,
+            Some(x) if x.kind == ChangeKind::Removed => ChangeKind::Restored,
+            Some(_) => return self,
+        };
+        self.history.push(Change { time, kind });
+        self
+    }
+
+    /// Record that the task was removed at `time`.
+    pub fn with_removed_at(mut self, time: DateTime<Utc>) -> Self {
+        self.history.push(Change {
+            time,
+            kind: ChangeKind::Removed,
+        });
+        self
+    }
+
+    /// Create the next occurrence of a repeating task. The new task is due on the first
+    /// date of the rule after both the current due date and `now`, at the same wall clock
+    /// time in the time zone of the task, or `zone` if it has none.
+    /// ```
+    /// use today::{recurrence::Recurrence, timezone::Zone, Task, TaskName};
+    /// use chrono::prelude::*;
+    ///
+    /// let task = Task::new(TaskName::new("Water plants").unwrap())
+    ///     .with_date_time(Utc.ymd(2022, 6, 20).and_hms(8, 0, 0))
+    ///     .with_repeat(Some(Recurrence::Every(3)));
+    ///
+    /// let now = Utc.ymd(2022, 6, 21).and_hms(12, 0, 0);
+    /// let next = task.next_occurrence(now, "UTC".parse::<Zone>().unwrap()).unwrap();
+    ///
+    /// assert_eq!(next.due(), Some(&Utc.ymd(2022, 6, 23).and_hms(8, 0, 0)));
+    /// assert_ne!(next.id(), task.id());
+    /// ```
+    pub fn next_occurrence(&self, now: DateTime<Utc>, zone: Zone) -> Option<Task> {
+        let repeat = self.repeat.as_ref()?;
+        let zone = self.zone_or(zone);
+        let today = zone.from_utc(&now).date();
+
+        let mut due = repeat.next_after(zone.from_utc(&self.due.unwrap_or(now)));
+        while due.date() <= today {
+            due = repeat.next_after(due);
+        }
+
+        Some(
+            Task::new(self.name.clone())
+                .with_date_time(zone.to_utc(&due))
+                .with_repeat(self.repeat.clone())
+                .with_priority(self.priority)
+                .with_tags(self.tags.iter().cloned())
+                .with_timezone(self.timezone)
+                .with_estimate(self.estimate),
+        )
+    }
 }
 
 impl std::fmt::Display for Task {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = &self.name;
+        let zone = self.timezone.unwrap_or_default();
         let date = self.due.map_or(String::from("ASAP"), |x| {
-            x.format("%Y-%m-%d %H:%M").to_string()
+            zone.from_utc(&x).format("%Y-%m-%d %H:%M").to_string()
         });
         write!(f, "{name} {date}")
     }
 }
 
-#[derive(Debug, Clone, Error)]
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
 pub enum TaskError {
     #[error("Invalid id '{0}'")]
     InvalidId(TaskId),
+    #[error("Invalid priority '{0}', expected one of none, low, medium or high")]
+    InvalidPriority(String),
+    #[error("Invalid tag '{0}', a tag starts with '+' or '@'")]
+    InvalidTag(String),
+    #[error("There is no checklist item number {0}")]
+    InvalidItem(usize),
+    #[error("'{0}' already has to wait for '{1}', they can't wait for each other")]
+    Cycle(String, String),
 }
 
 #[derive(Debug)]
@@ -208,8 +765,9 @@ impl TaskList {
         Self { tasks: Vec::new() }
     }
 
+    /// Add a new task, recording that it was created now.
     pub fn add(&mut self, task: Task) {
-        self.tasks.push(task);
+        self.tasks.push(task.with_created_at(Utc::now()));
     }
 
     pub fn add_range(&mut self, tasks: &[Task]) {
@@ -221,24 +779,84 @@ impl TaskList {
         self.tasks.retain(|x| x.id != *task_id);
     }
 
+    /// Replace the task with the same id, adding what changed to its history. Returns the
+    /// replaced task.
     pub fn edit(&mut self, task: Task) -> Result<Task, TaskError> {
         let filtered_tasks = self.tasks.iter().position(|x| x.id() == task.id());
 
         match filtered_tasks {
             None => Err(TaskError::InvalidId(task.id().clone())),
             Some(index) => {
+                let task = task.with_history_of(&self.tasks[index], Utc::now());
                 let old = std::mem::replace(&mut self.tasks[index], task);
                 Ok(old)
             }
         }
     }
 
-    /// Returns an iterator over all tasks that are due today
+    /// Make the task with the id `id` wait for the task with the id `on`. Fails if `on`
+    /// already waits for `id`, directly or through other tasks.
+    /// ```
+    /// use today::{Task, TaskList, TaskName};
+    ///
+    /// let write = Task::new(TaskName::new("Write report").unwrap());
+    /// let send = Task::new(TaskName::new("Send report").unwrap());
+    /// let mut tasks = TaskList::from(vec![write.clone(), send.clone()]);
+    ///
+    /// tasks.add_dependency(send.id(), write.id()).unwrap();
+    ///
+    /// assert!(tasks.is_blocked(tasks.iter().find(|x| x.id() == send.id()).unwrap()));
+    /// assert!(tasks.add_dependency(write.id(), send.id()).is_err());
+    /// ```
+    pub fn add_dependency(&mut self, id: &TaskId, on: &TaskId) -> Result<Task, TaskError> {
+        let other = self.get(on)?.name().to_owned();
+        let task = self.get(id)?.clone();
+        if id == on || self.waits_for(on, id) {
+            return Err(TaskError::Cycle(other, task.name().to_owned()));
+        }
+
+        self.edit(task.with_dependency(on.clone()))
+    }
+
+    /// Returns true if the task with the id `id` waits for the task with the id `other`,
+    /// directly or through other tasks.
+    pub fn waits_for(&self, id: &TaskId, other: &TaskId) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            if let Ok(task) = self.get(id) {
+                if task.depends_on.contains(other) {
+                    return true;
+                }
+                stack.extend(task.depends_on.iter());
+            }
+        }
+
+        false
+    }
+
+    /// Returns true if any of the tasks `task` depends on is not completed yet. Tasks that
+    /// have been removed don't block.
+    pub fn is_blocked(&self, task: &Task) -> bool {
+        task.is_blocked(&self.tasks)
+    }
+
+    fn get(&self, id: &TaskId) -> Result<&Task, TaskError> {
+        self.tasks
+            .iter()
+            .find(|x| x.id() == id)
+            .ok_or_else(|| TaskError::InvalidId(id.clone()))
+    }
+
+    /// Returns an iterator over all tasks that are due today and not yet completed
     pub fn today(&self) -> Today<'_> {
         Today::new(&self.tasks)
     }
 
-    pub fn iter(&self) -> std::slice::Iter<Task> {
+    pub fn iter(&self) -> std::slice::Iter<'_, Task> {
         self.tasks.iter()
     }
 
@@ -280,7 +898,7 @@ impl<'a> IntoIterator for &'a TaskList {
     type IntoIter = std::slice::Iter<'a, Task>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.as_slice().into_iter()
+        self.as_slice().iter()
     }
 }
 
@@ -298,7 +916,11 @@ impl std::iter::Extend<Task> for TaskList {
 
 pub struct Today<'a> {
     slice: &'a [Task],
-    today: Date<Utc>,
+    tasks: &'a [Task],
+    now: DateTime<Utc>,
+    zone: Zone,
+    completed: bool,
+    blocked: bool,
 }
 
 impl<'a> Iterator for Today<'a> {
@@ -306,9 +928,17 @@ impl<'a> Iterator for Today<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         for i in 0..self.slice.len() {
-            let is_due = self.slice[i].due().map_or(true, |x| self.today >= x.date());
-            if is_due {
-                let task = &self.slice[i];
+            let task = &self.slice[i];
+            let zone = task.zone_or(self.zone);
+            let today = zone.from_utc(&self.now).date();
+            let is_due = match (task.due(), task.repeat()) {
+                (Some(due), _) => today >= zone.from_utc(due).date(),
+                (None, Some(repeat)) => repeat.occurs_on(today),
+                (None, None) => true,
+            };
+            let is_shown = (self.completed || !task.is_completed())
+                && (self.blocked || !task.is_blocked(self.tasks));
+            if is_due && is_shown {
                 self.slice = &self.slice[i + 1..];
                 return Some(task);
             }
@@ -322,9 +952,34 @@ impl<'a> Today<'a> {
     pub fn new(slice: &'a [Task]) -> Self {
         Self {
             slice,
-            today: Utc::today(),
+            tasks: slice,
+            now: Utc::now(),
+            zone: Zone::Local,
+            completed: false,
+            blocked: false,
         }
     }
+
+    /// Decide what today is from the wall clock in `zone` rather than the system time zone,
+    /// for the tasks that don't have a time zone of their own.
+    pub fn with_timezone(self, zone: Zone) -> Self {
+        Self { zone, ..self }
+    }
+
+    /// Set the time that decides what today is.
+    pub fn with_now(self, now: DateTime<Utc>) -> Self {
+        Self { now, ..self }
+    }
+
+    /// Include tasks that have been completed.
+    pub fn with_completed(self, completed: bool) -> Self {
+        Self { completed, ..self }
+    }
+
+    /// Include tasks that wait for other tasks to be done.
+    pub fn with_blocked(self, blocked: bool) -> Self {
+        Self { blocked, ..self }
+    }
 }
 
 #[cfg(test)]
@@ -339,4 +994,125 @@ mod tests {
     fn new_taskname(input: &str) -> Option<TaskName> {
         TaskName::new(input)
     }
+
+    #[test_case("+project" => true)]
+    #[test_case("@context" => true)]
+    #[test_case("+" => false)]
+    #[test_case("word" => false)]
+    #[test_case("email@example.com" => false)]
+    fn new_tag(input: &str) -> bool {
+        Tag::new(input).is_some()
+    }
+
+    #[test]
+    fn without_tags_should_remove_tags_from_name_and_explicit_tags() {
+        let task = Task::new(TaskName::new("Buy  milk +groceries @store").unwrap())
+            .with_tags(Tag::new("+errands"))
+            .with_tags(Tag::new("@car"));
+
+        let task = task.without_tags(&["groceries", "+errands"]);
+
+        assert_eq!(task.name(), "Buy milk @store");
+        assert_eq!(
+            task.tags()
+                .into_iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+            ["@car", "@store"]
+        );
+    }
+
+    #[test]
+    fn without_tags_should_keep_name_made_of_tags() {
+        let task = Task::new(TaskName::new("+inbox").unwrap()).without_tags(&["inbox"]);
+
+        assert_eq!(task.name(), "+inbox");
+    }
+
+    #[test]
+    fn today_should_skip_completed_tasks() {
+        let done = Task::new(TaskName::new("Done").unwrap()).with_completed(Some(Utc::now()));
+        let pending = Task::new(TaskName::new("Pending").unwrap());
+        let tasks = TaskList::from(vec![done, pending]);
+
+        let names = tasks.today().map(|x| x.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Pending"]);
+
+        let all = tasks.today().with_completed(true).count();
+        assert_eq!(all, 2);
+    }
+
+    #[test]
+    fn today_should_use_time_zone_of_task() {
+        let utc = Zone::Named(chrono_tz::UTC);
+        let tokyo = Zone::Named(chrono_tz::Asia::Tokyo);
+        // Already the 16th in Tokyo
+        let now = Utc.ymd(2022, 6, 15).and_hms(20, 0, 0);
+        let due = Utc.ymd(2022, 6, 16).and_hms(1, 0, 0);
+        let own = Task::new(TaskName::new("Own zone").unwrap())
+            .with_date_time(due)
+            .with_timezone(Some(tokyo));
+        let user = Task::new(TaskName::new("User zone").unwrap()).with_date_time(due);
+        let tasks = TaskList::from(vec![own, user]);
+
+        let names = tasks
+            .today()
+            .with_timezone(utc)
+            .with_now(now)
+            .map(|x| x.name())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["Own zone"]);
+    }
+
+    #[test]
+    fn today_should_skip_blocked_tasks() {
+        let first = Task::new(TaskName::new("First").unwrap());
+        let second =
+            Task::new(TaskName::new("Second").unwrap()).with_dependency(first.id().clone());
+        let mut tasks = TaskList::from(vec![first.clone(), second]);
+
+        let names = tasks.today().map(|x| x.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["First"]);
+        assert_eq!(tasks.today().with_blocked(true).count(), 2);
+
+        tasks.edit(first.with_completed(Some(Utc::now()))).unwrap();
+        let names = tasks.today().map(|x| x.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Second"]);
+    }
+
+    #[test]
+    fn add_dependency_should_fail_given_cycle_through_other_tasks() {
+        let tasks = ["First", "Second", "Third"].map(|x| Task::new(TaskName::new(x).unwrap()));
+        let [first, second, third] = tasks.clone().map(|x| x.id().clone());
+        let mut tasks = TaskList::from(tasks.to_vec());
+
+        tasks.add_dependency(&second, &first).unwrap();
+        tasks.add_dependency(&third, &second).unwrap();
+
+        assert_eq!(
+            tasks.add_dependency(&first, &third),
+            Err(TaskError::Cycle("Third".to_owned(), "First".to_owned()))
+        );
+        assert!(tasks.add_dependency(&first, &first).is_err());
+        assert!(tasks.waits_for(&third, &first));
+        assert!(!tasks.waits_for(&first, &third));
+    }
+
+    #[test]
+    fn next_occurrence_should_keep_wall_clock_time_across_daylight_saving() {
+        let zone = Zone::Named(chrono_tz::Europe::Stockholm);
+        let task = Task::new(TaskName::new("Standup").unwrap())
+            .with_date_time(Utc.ymd(2022, 3, 26).and_hms(8, 0, 0))
+            .with_repeat(Some(Recurrence::Daily));
+
+        let now = Utc.ymd(2022, 3, 26).and_hms(12, 0, 0);
+        let next = task.next_occurrence(now, zone).unwrap();
+        assert_eq!(next.due(), Some(&Utc.ymd(2022, 3, 27).and_hms(7, 0, 0)));
+
+        let own_zone = task.with_timezone(Some(Zone::Named(chrono_tz::UTC)));
+        let next = own_zone.next_occurrence(now, zone).unwrap();
+        assert_eq!(next.due(), Some(&Utc.ymd(2022, 3, 27).and_hms(8, 0, 0)));
+        assert_eq!(next.timezone(), own_zone.timezone());
+    }
 }
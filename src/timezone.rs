@@ -0,0 +1,163 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use chrono::{prelude::*, Duration, LocalResult};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+/// The time zone dates are entered and shown in. Due dates are always stored in UTC,
+/// a zone decides what wall clock time they correspond to.
+///
+/// The textual form is either `local`, the time zone of the system, or the name of a
+/// zone in the IANA database such as `Europe/Stockholm`.
+/// ```
+/// use today::timezone::Zone;
+/// use chrono::prelude::*;
+///
+/// let zone = "Europe/Stockholm".parse::<Zone>().unwrap();
+/// let due = zone.to_utc(&NaiveDate::from_ymd(2022, 6, 15).and_hms(9, 0, 0));
+///
+/// assert_eq!(due, Utc.ymd(2022, 6, 15).and_hms(7, 0, 0));
+/// assert_eq!(zone.from_utc(&due).time(), NaiveTime::from_hms(9, 0, 0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Zone {
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum ZoneError {
+    #[error("Unknown time zone '{0}', expected 'local' or a name like 'Europe/Stockholm'")]
+    Unknown(String),
+}
+
+impl Zone {
+    /// Convert a wall clock time in this zone to UTC.
+    ///
+    /// A time that happens twice when the clocks are turned back resolves to the first
+    /// one. A time that is skipped when the clocks are turned forward resolves to the
+    /// same wall clock time after the change.
+    pub fn to_utc(&self, local: &NaiveDateTime) -> DateTime<Utc> {
+        let resolve = |x: &NaiveDateTime| match self {
+            Zone::Local => earliest(Local.from_local_datetime(x)),
+            Zone::Named(tz) => earliest(tz.from_local_datetime(x)),
+        };
+
+        resolve(local)
+            .or_else(|| resolve(&(*local + Duration::hours(1))))
+            .unwrap_or_else(|| Utc.from_utc_datetime(local))
+    }
+
+    /// Convert a point in time to the wall clock time in this zone.
+    pub fn from_utc(&self, utc: &DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Local => utc.with_timezone(&Local).naive_local(),
+            Zone::Named(tz) => utc.with_timezone(tz).naive_local(),
+        }
+    }
+
+    /// The current wall clock time in this zone.
+    pub fn now(&self) -> NaiveDateTime {
+        self.from_utc(&Utc::now())
+    }
+
+    /// The current date in this zone.
+    pub fn today(&self) -> NaiveDate {
+        self.now().date()
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Zone::Local => "local",
+            Zone::Named(tz) => tz.name(),
+        }
+    }
+}
+
+fn earliest<T: TimeZone>(result: LocalResult<DateTime<T>>) -> Option<DateTime<Utc>> {
+    result.earliest().map(|x| x.with_timezone(&Utc))
+}
+
+impl PartialOrd for Zone {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Zone {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.name().cmp(other.name())
+    }
+}
+
+impl std::fmt::Display for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Zone {
+    type Err = ZoneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("local") {
+            Ok(Zone::Local)
+        } else {
+            s.parse::<Tz>()
+                .map(Zone::Named)
+                .map_err(|_| ZoneError::Unknown(s.to_owned()))
+        }
+    }
+}
+
+impl Serialize for Zone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Zone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn stockholm() -> Zone {
+        Zone::Named(chrono_tz::Europe::Stockholm)
+    }
+
+    #[test_case("local" => Ok(Zone::Local))]
+    #[test_case("UTC" => Ok(Zone::Named(chrono_tz::UTC)))]
+    #[test_case(" America/New_York " => Ok(Zone::Named(chrono_tz::America::New_York)))]
+    #[test_case("Mars/Olympus" => Err(ZoneError::Unknown("Mars/Olympus".to_owned())))]
+    fn parse_zone(input: &str) -> Result<Zone, ZoneError> {
+        input.parse()
+    }
+
+    #[test_case((2022, 1, 15, 9) => Utc.ymd(2022, 1, 15).and_hms(8, 0, 0); "winter time")]
+    #[test_case((2022, 6, 15, 9) => Utc.ymd(2022, 6, 15).and_hms(7, 0, 0); "summer time")]
+    #[test_case((2022, 3, 27, 2) => Utc.ymd(2022, 3, 27).and_hms(1, 0, 0); "skipped hour")]
+    #[test_case((2022, 10, 30, 2) => Utc.ymd(2022, 10, 30).and_hms(0, 0, 0); "repeated hour")]
+    fn to_utc(local: (i32, u32, u32, u32)) -> DateTime<Utc> {
+        let local = NaiveDate::from_ymd(local.0, local.1, local.2).and_hms(local.3, 0, 0);
+        stockholm().to_utc(&local)
+    }
+
+    #[test]
+    fn from_utc_should_cross_date_boundary() {
+        let zone = Zone::Named(chrono_tz::Asia::Tokyo);
+        let local = zone.from_utc(&Utc.ymd(2022, 6, 15).and_hms(20, 0, 0));
+
+        assert_eq!(local, NaiveDate::from_ymd(2022, 6, 16).and_hms(5, 0, 0));
+    }
+}
@@ -0,0 +1,167 @@
+use std::{convert::TryFrom, str::FromStr};
+
+use chrono::{prelude::*, Duration};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A length of time in whole minutes, used for estimates.
+///
+/// The textual form is hours and minutes like `1h30m`, `2h` or `45m`, a bare number is
+/// minutes.
+/// ```
+/// use today::tracking::Minutes;
+///
+/// let estimate = "1h30m".parse::<Minutes>().unwrap();
+///
+/// assert_eq!(estimate, Minutes::new(90));
+/// assert_eq!(estimate.to_string(), "1h30m");
+/// assert_eq!("45".parse::<Minutes>().unwrap().to_string(), "45m");
+/// ```
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct Minutes(u32);
+
+impl Minutes {
+    pub fn new(minutes: u32) -> Self {
+        Self(minutes)
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+
+    pub fn to_duration(self) -> Duration {
+        Duration::minutes(self.0.into())
+    }
+}
+
+impl std::fmt::Display for Minutes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.0 / 60, self.0 % 60) {
+            (0, minutes) => write!(f, "{minutes}m"),
+            (hours, 0) => write!(f, "{hours}h"),
+            (hours, minutes) => write!(f, "{hours}h{minutes}m"),
+        }
+    }
+}
+
+impl FromStr for Minutes {
+    type Err = TrackingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TrackingError::InvalidDuration(s.to_owned());
+        let text = s.trim().to_lowercase();
+        if text.is_empty() {
+            return Err(invalid());
+        }
+        if let Ok(minutes) = text.parse::<u32>() {
+            return Ok(Self(minutes));
+        }
+
+        let (hours, rest) = match text.split_once('h') {
+            Some((hours, rest)) => (hours.parse::<u32>().map_err(|_| invalid())?, rest),
+            None => (0, text.as_str()),
+        };
+        let minutes = match rest {
+            "" => 0,
+            x => x
+                .strip_suffix('m')
+                .and_then(|x| x.parse::<u32>().ok())
+                .ok_or_else(invalid)?,
+        };
+
+        hours
+            .checked_mul(60)
+            .and_then(|x| x.checked_add(minutes))
+            .map(Self)
+            .ok_or_else(invalid)
+    }
+}
+
+/// A duration as hours and minutes, rounded down to whole minutes.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    Minutes(u32::try_from(minutes).unwrap_or(u32::MAX)).to_string()
+}
+
+/// A period of time spent on a task. An entry without an end is still running.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeEntry {
+    pub start: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl TimeEntry {
+    pub fn is_running(&self) -> bool {
+        self.end.is_none()
+    }
+
+    /// The part of the entry between `from` and `to`, a running entry runs until `now`.
+    pub fn duration_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Duration {
+        let start = self.start.max(from);
+        let end = self.end.unwrap_or(now).min(to);
+
+        (end - start).max(Duration::zero())
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum TrackingError {
+    #[error("Invalid duration '{0}', expected e.g. '1h30m', '2h' or '45m'")]
+    InvalidDuration(String),
+    #[error("Time is already being tracked for '{0}'")]
+    AlreadyRunning(String),
+    #[error("Time is not being tracked for '{0}'")]
+    NotRunning(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("2h" => Ok(Minutes(120)))]
+    #[test_case("1h5m" => Ok(Minutes(65)))]
+    #[test_case("90m" => Ok(Minutes(90)))]
+    #[test_case(" 15 " => Ok(Minutes(15)))]
+    #[test_case("1.5h" => Err(TrackingError::InvalidDuration("1.5h".to_owned())))]
+    #[test_case("h" => Err(TrackingError::InvalidDuration("h".to_owned())))]
+    #[test_case("" => Err(TrackingError::InvalidDuration("".to_owned())))]
+    fn parse_minutes(input: &str) -> Result<Minutes, TrackingError> {
+        input.parse()
+    }
+
+    #[test]
+    fn duration_between_should_clip_entry_to_period() {
+        let at = |hour| Utc.ymd(2022, 6, 15).and_hms(hour, 0, 0);
+        let entry = TimeEntry {
+            start: at(8),
+            end: Some(at(12)),
+        };
+        let running = TimeEntry {
+            start: at(14),
+            end: None,
+        };
+
+        assert_eq!(
+            entry.duration_between(at(10), at(20), at(20)),
+            Duration::hours(2)
+        );
+        assert_eq!(
+            entry.duration_between(at(13), at(20), at(20)),
+            Duration::zero()
+        );
+        assert_eq!(
+            running.duration_between(at(0), at(23), at(15)),
+            Duration::hours(1)
+        );
+    }
+}
@@ -6,13 +6,12 @@ use inquire::{
     ui::{RenderConfig, Styled},
     validator::StringValidator,
     CustomType,
-    DateSelect,
     Select,
     Text,
 };
-use std::fmt::Display;
+use std::{env, fmt::Display, fs, io::Write, process};
 
-use today::{Task, TaskName};
+use today::{parser::date, recurrence::Recurrence, task::Priority, timezone::Zone, Task, TaskName};
 
 pub mod writers;
 
@@ -53,23 +52,51 @@ pub fn menu() -> anyhow::Result<MenuOption> {
     Ok(selected)
 }
 
-pub fn prompt_task() -> anyhow::Result<Task> {
+/// Ask for all the parts of a task, the due date is read as a wall clock time in `zone`.
+pub fn prompt_task(zone: Zone) -> anyhow::Result<Task> {
     let name = prompt_name()?;
 
-    let due = prompt_due()?;
+    let due = prompt_due(zone)?;
 
     let task = Task::new(TaskName::new(&name).unwrap());
-    let task = if let Some(date) = due {
-        let time = prompt_time()?;
-        let due = date.and_time(time);
-        task.with_date_time(Utc.from_local_datetime(&due).unwrap())
+    let task = if let Some(due) = due {
+        task.with_date_time(zone.to_utc(&due))
     } else {
         task
     };
 
+    let task = task
+        .with_repeat(prompt_repeat()?)
+        .with_priority(prompt_priority()?);
+
     Ok(task)
 }
 
+pub fn prompt_priority() -> InquireResult<Priority> {
+    let options = vec![
+        Priority::None,
+        Priority::Low,
+        Priority::Medium,
+        Priority::High,
+    ];
+
+    Select::new("Priority:", options)
+        .with_vim_mode(true)
+        .prompt()
+}
+
+pub fn prompt_repeat() -> InquireResult<Option<Recurrence>> {
+    let repeat_style =
+        RenderConfig::default_colored().with_canceled_prompt_indicator(Styled::new("Never"));
+
+    CustomType::<Recurrence>::new("Repeat:")
+        .with_placeholder("daily, weekly:mon,fri, monthly:15, every:3")
+        .with_help_message("Press ESC if the task does not repeat")
+        .with_error_message("Not a valid repeat rule")
+        .with_render_config(repeat_style)
+        .prompt_skippable()
+}
+
 pub fn prompt_task_remove(options: &[Task]) -> anyhow::Result<Option<Task>> {
     let formatter: OptionFormatter<Task> = &|x| x.value.name().to_owned();
     let selected = Select::new("Which task do you want to remove?", options.to_vec())
@@ -80,41 +107,54 @@ pub fn prompt_task_remove(options: &[Task]) -> anyhow::Result<Option<Task>> {
     Ok(selected)
 }
 
-pub fn prompt_time() -> InquireResult<NaiveTime> {
-    let time_style =
-        RenderConfig::default_colored().with_canceled_prompt_indicator(Styled::new("00:00:00"));
-
-    loop {
-        let time = CustomType::<NaiveTime>::new("Time:")
-            .with_default((NaiveTime::from_hms(0, 0, 0), &|x| format!("{}", x)))
-            .with_placeholder("HH:MM:SS")
-            .with_parser(&|x| match NaiveTime::parse_from_str(x, "%H:%M") {
-                Ok(time) => Ok(time),
-                Err(_) => Err(()),
-            })
-            .with_render_config(time_style)
-            .prompt();
-
-        match time {
-            Ok(t) => return Ok(t),
-            Err(InquireError::OperationCanceled) => {}
-            Err(e) => panic!("Unrecoverable error: {}", e),
-        }
-    }
-}
-
-pub fn prompt_due() -> InquireResult<Option<NaiveDate>> {
+pub fn prompt_due(zone: Zone) -> InquireResult<Option<NaiveDateTime>> {
     let date_style = RenderConfig::default_colored()
         .with_canceled_prompt_indicator(Styled::new("As soon as possible"));
+    let now = zone.now();
+    let parser = move |x: &str| date::parse(x, now).map_err(|_| ());
 
-    DateSelect::new("Due date:")
+    CustomType::<NaiveDateTime>::new("Due date:")
+        .with_placeholder("tomorrow 9:00, next friday, +2w, YYYY-MM-DD HH:MM")
         .with_help_message("Press ESC to set task to be due as soon as possible")
-        .with_min_date(Utc::today().naive_utc())
-        .with_vim_mode(true)
+        .with_formatter(&|x| x.format("%Y-%m-%d %H:%M").to_string())
+        .with_parser(&parser)
+        .with_error_message("Not a valid date")
         .with_render_config(date_style)
         .prompt_skippable()
 }
 
+/// Let the user change `text` in the editor in `$VISUAL` or `$EDITOR`, `vi` if neither is
+/// set. `suffix` is the file name extension, so that the editor can pick the right mode.
+pub fn edit_text(text: &str, suffix: &str) -> anyhow::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // The editor may be given with arguments, e.g. 'code --wait'
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("The editor in $EDITOR is empty"))?;
+
+    let mut file = tempfile::Builder::new()
+        .prefix("today-")
+        .suffix(suffix)
+        .tempfile()?;
+    file.write_all(text.as_bytes())?;
+    file.flush()?;
+
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .map_err(|e| anyhow::anyhow!("Unable to start the editor '{editor}': {e}"))?;
+    if !status.success() {
+        anyhow::bail!("The editor '{editor}' exited with {status}, nothing was changed");
+    }
+
+    // Editors may replace the file rather than write to it, so it is read by its path
+    Ok(fs::read_to_string(file.path())?)
+}
+
 pub fn prompt_name() -> anyhow::Result<String> {
     let name_validator: StringValidator = &|input: &str| {
         if input.chars().any(|x| !x.is_whitespace()) {